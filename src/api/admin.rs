@@ -0,0 +1,285 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+use crate::api::{auctions, bazaar};
+use crate::api::auth::{admin, save_tokens};
+use crate::api::error::{ApiError, ErrorBody};
+use crate::api::json_body;
+use crate::api::users::save;
+use crate::auth::TOKENS;
+use crate::calendar::calendar::Visibility;
+use crate::calendar::database::DATABASE;
+use crate::calendar::election::refresh_election;
+use crate::config::config;
+use crate::logger::{log_levels, set_log_levels, LogLevels};
+use crate::notifications::{due_reminders, enqueue, ReminderKey, DELIVERIES, KEEP_DELIVERIES_DAYS};
+use crate::shutdown::flush_state;
+
+/// Longest range the Skyblock calendar can be regenerated for at once.
+const MAX_REGENERATE_DAYS: i64 = 31;
+/// Longest window of reminders that can be replayed at once.
+const MAX_REPLAY_DAYS: i64 = 7;
+
+pub fn admin_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let admin_path = warp::path("admin");
+
+    // POST /admin/refresh/{source}
+    let refresh = admin_path
+        .and(warp::path("refresh"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(admin())
+        .then(refresh_handler);
+
+    // POST /admin/calendar/regenerate
+    let regenerate = admin_path
+        .and(warp::path("calendar"))
+        .and(warp::path("regenerate"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(admin())
+        .and(json_body())
+        .then(regenerate_handler);
+
+    // GET /admin/database
+    let database = admin_path
+        .and(warp::path("database"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(admin())
+        .then(database_handler);
+
+    // PUT /admin/calendars/{calendar}
+    let edit_calendar = admin_path
+        .and(warp::path("calendars"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(admin())
+        .and(json_body())
+        .then(edit_calendar_handler);
+
+    // DELETE /admin/users/{user}
+    let remove_user = admin_path
+        .and(warp::path("users"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(admin())
+        .then(remove_user_handler);
+
+    // GET /admin/log
+    let log = admin_path
+        .and(warp::path("log"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(admin())
+        .then(log_handler);
+
+    // PUT /admin/log
+    let set_log = admin_path
+        .and(warp::path("log"))
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(admin())
+        .and(json_body())
+        .then(set_log_handler);
+
+    // POST /admin/snapshot
+    let snapshot = admin_path
+        .and(warp::path("snapshot"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(admin())
+        .then(snapshot_handler);
+
+    // POST /admin/reminders/replay
+    let replay = admin_path
+        .and(warp::path("reminders"))
+        .and(warp::path("replay"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(admin())
+        .and(json_body())
+        .then(replay_handler);
+
+    refresh
+        .or(regenerate)
+        .or(database)
+        .or(edit_calendar)
+        .or(remove_user)
+        .or(log)
+        .or(set_log)
+        .or(snapshot)
+        .or(replay)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct TimeRange {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+impl TimeRange {
+    fn validate(&self, max_days: i64) -> Result<(), ApiError> {
+        if self.from >= self.to {
+            return Err(ApiError::BadRequest("from must be before to".to_string()));
+        }
+        if self.to - self.from > Duration::days(max_days) {
+            return Err(ApiError::BadRequest(format!("The range must not be longer than {} days", max_days)));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct CalendarEdit {
+    title: Option<String>,
+    description: Option<String>,
+    visibility: Option<Visibility>,
+}
+
+/// Runs a poller now and drops the cached responses built from its data.
+#[utoipa::path(post, path = "/admin/refresh/{source}", tag = "admin", security(("bearer" = ["admin"])),
+    params(("source" = String, Path, description = "`election`, `auctions` or `bazaar`")),
+    responses((status = 200, body = Object, example = json!({ "refreshed": "election" })),
+        (status = 401, body = ErrorBody), (status = 403, body = ErrorBody), (status = 404, body = ErrorBody),
+        (status = 502, body = ErrorBody)))]
+async fn refresh_handler(source: String, _admin: Uuid) -> Result<impl Reply, ApiError> {
+    match source.as_str() {
+        "election" => refresh_election().await.map_err(ApiError::upstream)?,
+        "auctions" => auctions::refresh().await?,
+        "bazaar" => bazaar::refresh().await?,
+        _ => return Err(ApiError::NotFound(format!("Nothing called '{}' can be refreshed", source))),
+    }
+    Ok(warp::reply::json(&serde_json::json!({ "refreshed": source })))
+}
+
+/// Replaces the Skyblock calendar with the events of a range that includes now, e.g. to cover more
+/// of the future than the automatic refresh. Past and future ranges are generated on demand by queries.
+#[utoipa::path(post, path = "/admin/calendar/regenerate", tag = "admin", security(("bearer" = ["admin"])),
+    request_body = TimeRange,
+    responses((status = 200, body = Object, example = json!({ "events": 1234 })),
+        (status = 400, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn regenerate_handler(_admin: Uuid, range: TimeRange) -> Result<impl Reply, ApiError> {
+    range.validate(MAX_REGENERATE_DAYS)?;
+    let now = Utc::now();
+    if !(range.from <= now && now < range.to) {
+        return Err(ApiError::BadRequest("The range must include the current time".to_string()));
+    }
+    let mut database = DATABASE.write().unwrap();
+    database.regenerate_skyblock_calendar(range.from, range.to);
+    let events: usize = database.public_calendars().iter().map(|calendar| calendar.list_events().len()).sum();
+    Ok(warp::reply::json(&serde_json::json!({ "events": events })))
+}
+
+/// Every user with their calendars, without events.
+#[utoipa::path(get, path = "/admin/database", tag = "admin", security(("bearer" = ["admin"])),
+    responses((status = 200, body = Vec<crate::calendar::database::UserSummary>),
+        (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn database_handler(_admin: Uuid) -> impl Reply {
+    warp::reply::json(&DATABASE.read().unwrap().user_summaries())
+}
+
+/// Changes the title, description or visibility of anyone's calendar but the generated Skyblock one.
+#[utoipa::path(put, path = "/admin/calendars/{calendar}", tag = "admin", security(("bearer" = ["admin"])),
+    params(("calendar" = Uuid, Path)), request_body = CalendarEdit,
+    responses((status = 200, body = crate::calendar::calendar::CalendarSummary), (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody), (status = 403, body = ErrorBody), (status = 404, body = ErrorBody)))]
+async fn edit_calendar_handler(calendar_id: Uuid, _admin: Uuid, edit: CalendarEdit) -> Result<impl Reply, ApiError> {
+    let (summary, snapshot) = {
+        let mut database = DATABASE.write().unwrap();
+        let summary = database.edit_calendar(calendar_id, edit.title, edit.description, edit.visibility)?;
+        (summary, database.snapshot())
+    };
+    save(snapshot).await?;
+    Ok(warp::reply::json(&summary))
+}
+
+/// Deletes a user with their calendars and revokes their tokens.
+#[utoipa::path(delete, path = "/admin/users/{user}", tag = "admin", security(("bearer" = ["admin"])),
+    params(("user" = Uuid, Path)),
+    responses((status = 200, body = Object, example = json!({ "status": "removed", "revoked_tokens": 2 })),
+        (status = 400, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody),
+        (status = 404, body = ErrorBody)))]
+async fn remove_user_handler(user_id: Uuid, _admin: Uuid) -> Result<impl Reply, ApiError> {
+    let snapshot = {
+        let mut database = DATABASE.write().unwrap();
+        database.remove_user(user_id)?;
+        database.snapshot()
+    };
+    save(snapshot).await?;
+    let (revoked, snapshot) = {
+        let mut tokens = TOKENS.write().unwrap();
+        let revoked = tokens.revoke_user(user_id);
+        (revoked, tokens.snapshot())
+    };
+    save_tokens(snapshot).await?;
+    Ok(warp::reply::json(&serde_json::json!({ "status": "removed", "revoked_tokens": revoked })))
+}
+
+/// The log levels in effect.
+#[utoipa::path(get, path = "/admin/log", tag = "admin", security(("bearer" = ["admin"])),
+    responses((status = 200, body = LogLevels), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn log_handler(_admin: Uuid) -> Result<impl Reply, ApiError> {
+    log_levels().map(|levels| warp::reply::json(&levels)).ok_or(ApiError::Internal)
+}
+
+/// Changes the log levels until the next restart.
+#[utoipa::path(put, path = "/admin/log", tag = "admin", security(("bearer" = ["admin"])), request_body = LogLevels,
+    responses((status = 200, body = LogLevels), (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn set_log_handler(_admin: Uuid, levels: LogLevels) -> Result<impl Reply, ApiError> {
+    set_log_levels(levels.clone()).map_err(ApiError::BadRequest)?;
+    log::info!("Log levels changed to {} with module overrides {:?}", levels.level, levels.modules);
+    Ok(warp::reply::json(&levels))
+}
+
+/// Saves users, tokens, elections, contests and reminder deliveries to the data directory now.
+#[utoipa::path(post, path = "/admin/snapshot", tag = "admin", security(("bearer" = ["admin"])),
+    responses((status = 200, body = Object, example = json!({ "status": "saved" })),
+        (status = 401, body = ErrorBody), (status = 403, body = ErrorBody), (status = 500, body = ErrorBody)))]
+async fn snapshot_handler(_admin: Uuid) -> Result<impl Reply, ApiError> {
+    let failed = tokio::task::spawn_blocking(flush_state).await.map_err(|_| ApiError::Internal)?;
+    if failed > 0 {
+        return Err(ApiError::Storage(format!("{} of the stores could not be saved", failed)));
+    }
+    Ok(warp::reply::json(&serde_json::json!({ "status": "saved" })))
+}
+
+/// Queues the reminders of public calendars that were due in a past window but never delivered,
+/// e.g. while the server was down or a sink failed. They go out one at a time, at the pace of the
+/// reminder scheduler. Only the events' own reminder times count, not subscriptions' overrides.
+#[utoipa::path(post, path = "/admin/reminders/replay", tag = "admin", security(("bearer" = ["admin"])),
+    request_body = TimeRange,
+    responses((status = 202, body = Object, example = json!({ "due": 12, "already_delivered": 9, "queued": 3 })),
+        (status = 400, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn replay_handler(_admin: Uuid, range: TimeRange) -> Result<impl Reply, ApiError> {
+    range.validate(MAX_REPLAY_DAYS)?;
+    let now = Utc::now();
+    if range.to > now {
+        return Err(ApiError::BadRequest("Only reminders that were already due can be replayed".to_string()));
+    }
+    if range.from < now - Duration::days(KEEP_DELIVERIES_DAYS) {
+        return Err(ApiError::BadRequest(format!("Deliveries are only remembered for {} days", KEEP_DELIVERIES_DAYS)));
+    }
+    if config().notifications.is_empty() {
+        return Err(ApiError::BadRequest("No notification sinks are configured".to_string()));
+    }
+
+    let due = due_reminders(range.from, range.to);
+    let already_delivered = {
+        let deliveries = DELIVERIES.read().unwrap();
+        due.iter().filter(|reminder| deliveries.contains(&ReminderKey::of(reminder))).count()
+    };
+    let total = due.len();
+    let queued = enqueue(due);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "due": total, "already_delivered": already_delivered, "queued": queued })),
+        StatusCode::ACCEPTED,
+    ))
+}
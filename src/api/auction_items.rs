@@ -1,11 +1,15 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use utoipa::ToSchema;
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AuctionItem {
     Pet(Pet),
     Armor(Armor),
 }
+#[allow(dead_code)]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Pet {
     name: String,
@@ -14,6 +18,7 @@ pub struct Pet {
     candy: i8,
 }
 
+#[allow(dead_code)]
 impl Pet {
     pub fn new(name: String, level: i16, exp: i32, candy: i8) -> Self {
         Pet {
@@ -25,6 +30,7 @@ impl Pet {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ArmorType {
     Helmet,
@@ -33,6 +39,7 @@ pub enum ArmorType {
     Boots,
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Armor {
     name: String,
@@ -45,7 +52,9 @@ pub struct Armor {
     star: i16,
 }
 
+#[allow(dead_code)]
 impl Armor {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         name: String,
         armor_type: ArmorType,
@@ -69,7 +78,8 @@ impl Armor {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// An auction as Hypixel lists it.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct AuctionListing {
     uuid: String,
     auctioneer: String,
@@ -95,11 +105,28 @@ pub struct AuctionListing {
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct HypixelAuction {
     success: bool,
     page: usize,
-    totalPages: usize,
-    totalAuctions: usize,
-    lastUpdated: usize,
+    total_pages: usize,
+    total_auctions: usize,
+    last_updated: usize,
     pub auctions: Vec<AuctionListing>,
 }
+
+impl HypixelAuction {
+    pub fn total_pages(&self) -> usize {
+        self.total_pages
+    }
+
+    /// Active auctions across every page.
+    pub fn total_auctions(&self) -> usize {
+        self.total_auctions
+    }
+
+    /// When Hypixel last regenerated the auction list.
+    pub fn last_updated(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(self.last_updated as i64).unwrap_or_default()
+    }
+}
@@ -1,56 +1,179 @@
-use crate::api::auction_items::{
-    Armor, ArmorType, AuctionItem, AuctionListing, HypixelAuction, Pet,
-};
-use chrono::Utc;
-use reqwest;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
-use std::sync::Mutex;
+use crate::api::auction_items::{AuctionListing, HypixelAuction};
+use crate::api::auth::authenticated;
+use crate::api::cache::cached;
+use crate::api::error::{ApiError, ErrorBody};
+use crate::api::stream::{self, list_format, ListFormat};
+use crate::api::json_body;
+use crate::auth::Scope;
+use crate::health::HEALTH;
+use crate::helpers::hypixel_get;
+use crate::metrics::METRICS;
+use crate::shutdown::ShutdownSignal;
+use crate::snapshots::{Snapshot, SNAPSHOTS};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use utoipa::IntoParams;
+use uuid::Uuid;
 use warp::{Filter, Rejection, Reply};
-static TRACKING: Mutex<Vec<String>> = Mutex::new(Vec::new());
-pub fn auctions_routes() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+/// Times the pages are fetched again when the auction list changes part way through.
+const MAX_FETCH_ATTEMPTS: usize = 2;
+
+/// Every active auction from the last complete fetch, shared with the responses streaming it.
+static AUCTIONS: RwLock<Option<StoredAuctions>> = RwLock::new(None);
+static TRACKING: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Mutex::default);
+
+struct StoredAuctions {
+    /// When Hypixel last regenerated the list.
+    updated: DateTime<Utc>,
+    auctions: Arc<Vec<AuctionListing>>,
+}
+
+pub(crate) fn tracked_count() -> usize {
+    TRACKING.lock().unwrap().len()
+}
+
+pub fn auctions_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     let auction_path = warp::path("auction");
 
     // GET /auction
-    let list_auctions = auction_path
+    let list_auctions = cached(Snapshot::Auctions, auction_path
         .and(warp::get())
         .and(warp::path::end())
-        .and_then(list_auctions_handler);
+        .then(list_auctions_handler));
+
+    // GET /auction/snapshot
+    let snapshot = auction_path
+        .and(warp::path("snapshot"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(list_format())
+        .then(auction_snapshot_handler);
 
     // POST /auction/track
     let track_auction = auction_path
         .and(warp::path("track"))
+        .and(warp::path::end())
         .and(warp::post())
-        .and(warp::body::json())
+        .and(authenticated(Scope::ManageTracking))
+        .and(json_body())
         .and_then(track_auction_handler);
 
     // GET /auction/lowestbin?item=ITEM_NAME
     let lowest_bin = auction_path
         .and(warp::path("lowestbin"))
+        .and(warp::path::end())
         .and(warp::get())
-        .and(warp::query::<String>())
-        .and_then(lowest_bin_handler);
+        .and(warp::query::<LowestBinQuery>())
+        .then(lowest_bin_handler);
+
+    list_auctions.or(snapshot).or(track_auction).or(lowest_bin)
+}
+
+/// The first page of active auctions, fetched from Hypixel.
+#[utoipa::path(get, path = "/auction", tag = "auction",
+    responses((status = 200, body = Vec<AuctionListing>), (status = 304, description = "Not modified"), (status = 502, body = ErrorBody)))]
+async fn list_auctions_handler() -> Result<impl Reply, ApiError> {
+    let first = fetch_auction_page(0).await?;
+    SNAPSHOTS.fetched(Snapshot::Auctions, first.last_updated());
+    Ok(warp::reply::json(&first.auctions))
+}
+
+/// Fetches the auctions again and starts a new version of the auction snapshot.
+pub(crate) async fn refresh() -> Result<(), ApiError> {
+    SNAPSHOTS.invalidate(Snapshot::Auctions);
+    fetch_auctions().await
+}
+
+/// Fetches every auction page and records them as the latest auction snapshot. The other pages
+/// are only fetched when Hypixel regenerated the list since the last fetch.
+async fn fetch_auctions() -> Result<(), ApiError> {
+    let mut attempts = 0;
+    let (updated, total) = loop {
+        let first = fetch_auction_page(0).await?;
+        let updated = first.last_updated();
+        let stored = AUCTIONS.read().unwrap().as_ref().filter(|stored| stored.updated == updated).map(|stored| stored.auctions.len());
+        if let Some(total) = stored {
+            break (updated, total);
+        }
+        match fetch_remaining_pages(first).await? {
+            Some(auctions) => {
+                let total = auctions.len();
+                *AUCTIONS.write().unwrap() = Some(StoredAuctions { updated, auctions: Arc::new(auctions) });
+                break (updated, total);
+            }
+            None if attempts < MAX_FETCH_ATTEMPTS => attempts += 1,
+            None => return Err(ApiError::Upstream("The auctions kept changing while they were fetched".to_string())),
+        }
+    };
+    METRICS.set_auction_snapshot_size(total);
+    METRICS.poller_succeeded("auctions");
+    SNAPSHOTS.fetched(Snapshot::Auctions, updated);
+    Ok(())
+}
+
+/// The auctions of `first` followed by those of the pages after it, or `None` when Hypixel
+/// regenerated the list part way through and the pages no longer fit together.
+async fn fetch_remaining_pages(first: HypixelAuction) -> Result<Option<Vec<AuctionListing>>, ApiError> {
+    let updated = first.last_updated();
+    let total_pages = first.total_pages();
+    let mut auctions = Vec::with_capacity(first.total_auctions());
+    auctions.extend(first.auctions);
+    for page in 1..total_pages {
+        let page = fetch_auction_page(page).await?;
+        if page.last_updated() != updated {
+            return Ok(None);
+        }
+        auctions.extend(page.auctions);
+    }
+    Ok(Some(auctions))
+}
 
-    list_auctions.or(track_auction).or(lowest_bin)
+/// Keeps the auction snapshot current, so readiness notices when Hypixel stops answering.
+pub async fn poll_auctions(every: std::time::Duration, mut shutdown: ShutdownSignal) {
+    let _running = HEALTH.poller_started("auctions");
+    let mut interval = tokio::time::interval(every);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.recv() => {
+                info!("Auction poller stopped");
+                return;
+            }
+        }
+        if let Err(e) = fetch_auctions().await {
+            error!("Failed to fetch the auctions: {}", e);
+        }
+    }
 }
 
-async fn list_auctions_handler() -> Result<impl Reply, Rejection> {
-    let x: HypixelAuction = reqwest::get("https://api.hypixel.net/v2/skyblock/auctions")
+async fn fetch_auction_page(page: usize) -> Result<HypixelAuction, ApiError> {
+    hypixel_get(&format!("v2/skyblock/auctions?page={}", page))
         .await
-        .unwrap()
+        .map_err(ApiError::upstream)?
         .json::<HypixelAuction>()
         .await
-        .unwrap();
-    println!("{:?}", x.auctions);
+        .map_err(ApiError::upstream)
+}
 
-    Ok(warp::reply::json(
-        &serde_json::json!({"test": format!("{:?}",x.auctions)}),
-    ))
+/// Every active auction as of the last poll, streamed in batches.
+#[utoipa::path(get, path = "/auction/snapshot", tag = "auction",
+    responses(
+        (status = 200, content((Vec<Object> = "application/json"), (Object = "application/x-ndjson"))),
+        (status = 502, body = ErrorBody)))]
+async fn auction_snapshot_handler(format: ListFormat) -> Result<impl Reply, ApiError> {
+    let auctions = AUCTIONS.read().unwrap().as_ref().map(|stored| stored.auctions.clone())
+        .ok_or_else(|| ApiError::Upstream("The auctions have not been fetched yet".to_string()))?;
+    Ok(stream::list_shared(format, auctions))
 }
 
-async fn track_auction_handler(item: String) -> Result<impl Reply, Rejection> {
-    TRACKING.lock().unwrap().push(item.clone());
+#[utoipa::path(post, path = "/auction/track", tag = "auction", security(("bearer" = ["manage_tracking"])),
+    request_body(content = String, description = "Item name, as a JSON string"),
+    responses((status = 200, body = Object), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn track_auction_handler(_user: Uuid, item: String) -> Result<impl Reply, Rejection> {
+    TRACKING.lock().unwrap().insert(item.clone());
     let response = serde_json::json!({
         "message": format!("Now tracking auctions for '{}'", item),
         "item": item,
@@ -60,27 +183,27 @@ async fn track_auction_handler(item: String) -> Result<impl Reply, Rejection> {
     Ok(warp::reply::json(&response))
 }
 
-async fn lowest_bin_handler(item: String) -> Result<impl Reply, Rejection> {
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LowestBinQuery {
+    /// Item name.
+    item: String,
+}
+
+#[utoipa::path(get, path = "/auction/lowestbin", tag = "auction", params(LowestBinQuery),
+    responses((status = 200, body = Object), (status = 404, body = ErrorBody)))]
+async fn lowest_bin_handler(LowestBinQuery { item }: LowestBinQuery) -> Result<impl Reply, ApiError> {
     let lowest_bins = HashMap::from([
         ("Dragon Sword".to_string(), 75000.0),
         ("Terminator".to_string(), 30000.0),
         ("Armor".to_string(), 45000.0),
     ]);
 
-    match lowest_bins.get(&item) {
-        Some(price) => Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "item": item,
-                "lowest_bin": price,
-                "last_updated": Utc::now()
-            })),
-            warp::http::StatusCode::OK,
-        )),
-        None => Ok(warp::reply::with_status(
-            warp::reply::json(&serde_json::json!({
-                "error": format!("No BIN data found for '{}'", item)
-            })),
-            warp::http::StatusCode::NOT_FOUND,
-        )),
-    }
+    let price = lowest_bins.get(&item)
+        .ok_or_else(|| ApiError::NotFound(format!("No BIN data found for '{}'", item)))?;
+    Ok(warp::reply::json(&serde_json::json!({
+        "item": item,
+        "lowest_bin": price,
+        "last_updated": Utc::now()
+    })))
 }
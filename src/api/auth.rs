@@ -0,0 +1,170 @@
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+use crate::api::error::{ApiError, ErrorBody};
+use crate::api::json_body;
+use crate::api::users::save;
+use crate::auth::{hash_password, verify_login, Scope, TokensSnapshot, TOKENS};
+use crate::calendar::database::DATABASE;
+use crate::config::config;
+
+/// Raw bearer token from the `Authorization` header.
+fn bearer_token() -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(|header: Option<String>| async move {
+        let Some(header) = header else {
+            return Err(ApiError::Unauthorized("Missing bearer token").reject());
+        };
+        match header.strip_prefix("Bearer ") {
+            Some(token) if !token.trim().is_empty() => Ok(token.trim().to_string()),
+            _ => Err(ApiError::Unauthorized("Authorization must be 'Bearer <token>'").reject()),
+        }
+    })
+}
+
+/// Resolves the user of the bearer token, which must carry `scope`. Privileged scopes are
+/// checked against `admin.users` on every request, so removing an id takes effect without
+/// revoking its tokens.
+pub fn authenticated(scope: Scope) -> impl Filter<Extract = (Uuid,), Error = Rejection> + Clone {
+    bearer_token().and_then(move |token: String| async move {
+        let tokens = TOKENS.read().unwrap();
+        let Some(token) = tokens.resolve(&token) else {
+            return Err(ApiError::Unauthorized("Invalid or revoked token").reject());
+        };
+        if !token.allows(scope) {
+            return Err(ApiError::MissingScope(scope).reject());
+        }
+        if scope.is_privileged() && !config().admin.is_admin(token.user) {
+            return Err(ApiError::Forbidden("You are no longer an admin".to_string()).reject());
+        }
+        Ok(token.user)
+    })
+}
+
+/// Resolves the user of a bearer token with the `admin` scope, who must still be an admin.
+pub fn admin() -> impl Filter<Extract = (Uuid,), Error = Rejection> + Clone {
+    authenticated(Scope::Admin)
+}
+
+pub fn auth_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let auth_path = warp::path("auth");
+
+    // POST /auth/register
+    let register = auth_path
+        .and(warp::path("register"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body())
+        .then(register_handler);
+
+    // POST /auth/tokens
+    let issue_token = auth_path
+        .and(warp::path("tokens"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(json_body())
+        .then(issue_token_handler);
+
+    // DELETE /auth/tokens
+    let revoke_token = auth_path
+        .and(warp::path("tokens"))
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(bearer_token())
+        .then(revoke_token_handler);
+
+    register
+        .or(issue_token)
+        .or(revoke_token)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct Credentials {
+    name: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct TokenRequest {
+    name: String,
+    password: String,
+    /// Shown when listing tokens, e.g. "discord bot".
+    #[serde(default)]
+    label: String,
+    scopes: Option<Vec<Scope>>,
+}
+
+#[utoipa::path(post, path = "/auth/register", tag = "auth", request_body = Credentials,
+    responses((status = 201, body = Object, example = json!({ "id": "9b2f2f8e-4c1e-4b55-9d6f-0d3c1c4c2a11" })), (status = 400, body = ErrorBody)))]
+async fn register_handler(credentials: Credentials) -> Result<impl Reply, ApiError> {
+    let name = DATABASE.read().unwrap().check_registration(&credentials.name, &credentials.password)?;
+    let hash = tokio::task::spawn_blocking(move || hash_password(&credentials.password))
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    let (id, snapshot) = {
+        let mut database = DATABASE.write().unwrap();
+        let id = database.register(&name, hash)?;
+        (id, database.snapshot())
+    };
+    save(snapshot).await?;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "id": id })),
+        StatusCode::CREATED,
+    ))
+}
+
+/// Writes `snapshot` off the async runtime.
+pub(crate) async fn save_tokens(snapshot: Result<TokensSnapshot, serde_json::Error>) -> Result<(), ApiError> {
+    tokio::task::spawn_blocking(move || snapshot?.save())
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map_err(ApiError::storage)
+}
+
+/// Issues a bearer token; it is only shown once.
+#[utoipa::path(post, path = "/auth/tokens", tag = "auth", request_body = TokenRequest,
+    responses((status = 201, body = Object, example = json!({ "token": "sbc_...", "user": "9b2f2f8e-4c1e-4b55-9d6f-0d3c1c4c2a11", "scopes": ["read_calendars", "write_calendars"] })),
+        (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn issue_token_handler(request: TokenRequest) -> Result<impl Reply, ApiError> {
+    let account = DATABASE.read().unwrap().credentials(&request.name);
+    let password = request.password;
+    let user = tokio::task::spawn_blocking(move || verify_login(account, &password))
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    let Some(user) = user else {
+        return Err(ApiError::Unauthorized("Unknown name or wrong password"));
+    };
+
+    let scopes = request.scopes.unwrap_or_else(|| Scope::DEFAULT.to_vec());
+    if !config().admin.is_admin(user) {
+        if let Some(scope) = scopes.iter().find(|scope| scope.is_privileged()) {
+            return Err(ApiError::Forbidden(format!("Only users listed in admin.users can get the '{}' scope", scope)));
+        }
+    }
+    let (token, snapshot) = {
+        let mut tokens = TOKENS.write().unwrap();
+        let token = tokens.issue(user, request.label, scopes.clone());
+        (token, tokens.snapshot())
+    };
+    save_tokens(snapshot).await?;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "token": token, "user": user, "scopes": scopes })),
+        StatusCode::CREATED,
+    ))
+}
+
+/// Revokes the bearer token the request is made with.
+#[utoipa::path(delete, path = "/auth/tokens", tag = "auth", security(("bearer" = [])),
+    responses((status = 200, body = Object), (status = 401, body = ErrorBody)))]
+async fn revoke_token_handler(token: String) -> Result<impl Reply, ApiError> {
+    let snapshot = {
+        let mut tokens = TOKENS.write().unwrap();
+        if !tokens.revoke(&token) {
+            return Err(ApiError::Unauthorized("Invalid or revoked token"));
+        }
+        tokens.snapshot()
+    };
+    save_tokens(snapshot).await?;
+    Ok(warp::reply::json(&serde_json::json!({ "status": "revoked" })))
+}
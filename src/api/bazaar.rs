@@ -1,57 +1,134 @@
-use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{LazyLock, Mutex, RwLock};
+use chrono::{DateTime, Utc};
+use log::{error, info};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
 use warp::{Filter, Rejection, Reply};
+use crate::api::auth::authenticated;
+use crate::api::cache::cached;
+use crate::api::error::{ApiError, ErrorBody};
+use crate::api::json_body;
+use crate::auth::Scope;
+use crate::health::HEALTH;
+use crate::helpers::hypixel_get;
+use crate::metrics::METRICS;
+use crate::shutdown::ShutdownSignal;
+use crate::snapshots::{Snapshot, SNAPSHOTS};
 
-pub fn bazaar_routes() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+/// The products of the last successful fetch, by name.
+static PRODUCTS: RwLock<Vec<BazaarItem>> = RwLock::new(Vec::new());
+static TRACKING: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Mutex::default);
+
+pub(crate) fn tracked_count() -> usize {
+    TRACKING.lock().unwrap().len()
+}
+
+pub fn bazaar_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     let bazaar_path = warp::path("bazaar");
 
     // GET /bazaar
-    let list_bazaar = bazaar_path
+    let list_bazaar = cached(Snapshot::Bazaar, bazaar_path
         .and(warp::get())
         .and(warp::path::end())
-        .and_then(list_bazaar_handler);
+        .then(list_bazaar_handler));
 
     // POST /bazaar/track
     let track_item = bazaar_path
         .and(warp::path("track"))
+        .and(warp::path::end())
         .and(warp::post())
-        .and(warp::body::json())
+        .and(authenticated(Scope::ManageTracking))
+        .and(json_body())
         .and_then(track_bazaar_item_handler);
 
     list_bazaar.or(track_item)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all(deserialize = "camelCase"))]
 struct BazaarItem {
+    #[serde(alias = "productId")]
     name: String,
     sell_price: f64,
-    sell_volume: i32,
-    sell_moving_week: i32,
-    sell_orders: i32,
+    sell_volume: i64,
+    sell_moving_week: i64,
+    sell_orders: i64,
     buy_price: f64,
-    buy_volume: i32,
-    buy_moving_week: i32,
-    buy_orders: i32,
-}
-
-async fn list_bazaar_handler() -> Result<impl Reply, Rejection> {
-    let sample_items = vec![
-        BazaarItem {
-            name: "CORRUPTED_BAIT".to_string(),
-            sell_price: 2.18318238828381,
-            sell_volume: 533299,
-            sell_moving_week: 266680,
-            sell_orders: 24,
-            buy_price: 908.4,
-            buy_volume: 16754,
-            buy_moving_week: 370265,
-            buy_orders: 18
+    buy_volume: i64,
+    buy_moving_week: i64,
+    buy_orders: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct HypixelProduct {
+    quick_status: BazaarItem,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HypixelBazaar {
+    last_updated: i64,
+    products: HashMap<String, HypixelProduct>,
+}
+
+/// Every bazaar product, from the last poll or fetched now if there was none yet.
+#[utoipa::path(get, path = "/bazaar", tag = "bazaar",
+    responses((status = 200, body = Vec<BazaarItem>), (status = 304, description = "Not modified"), (status = 502, body = ErrorBody)))]
+async fn list_bazaar_handler() -> Result<impl Reply, ApiError> {
+    if PRODUCTS.read().unwrap().is_empty() {
+        fetch_products().await?;
+    }
+    Ok(warp::reply::json(&*PRODUCTS.read().unwrap()))
+}
+
+/// Fetches the products again and starts a new version of the bazaar snapshot.
+pub(crate) async fn refresh() -> Result<(), ApiError> {
+    SNAPSHOTS.invalidate(Snapshot::Bazaar);
+    fetch_products().await
+}
+
+/// Fetches the products from Hypixel and records them as the latest bazaar snapshot.
+async fn fetch_products() -> Result<(), ApiError> {
+    let bazaar = hypixel_get("v2/skyblock/bazaar")
+        .await
+        .map_err(ApiError::upstream)?
+        .json::<HypixelBazaar>()
+        .await
+        .map_err(ApiError::upstream)?;
+    let mut products: Vec<BazaarItem> = bazaar.products.into_values().map(|product| product.quick_status).collect();
+    products.sort_by(|a, b| a.name.cmp(&b.name));
+
+    *PRODUCTS.write().unwrap() = products;
+    METRICS.poller_succeeded("bazaar");
+    SNAPSHOTS.fetched(Snapshot::Bazaar, DateTime::from_timestamp_millis(bazaar.last_updated).unwrap_or_default());
+    Ok(())
+}
+
+/// Keeps the bazaar products current.
+pub async fn poll_bazaar(every: std::time::Duration, mut shutdown: ShutdownSignal) {
+    let _running = HEALTH.poller_started("bazaar");
+    let mut interval = tokio::time::interval(every);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.recv() => {
+                info!("Bazaar poller stopped");
+                return;
+            }
+        }
+        if let Err(e) = fetch_products().await {
+            error!("Failed to fetch the bazaar: {}", e);
         }
-    ];
-    Ok(warp::reply::json(&sample_items))
+    }
 }
 
-async fn track_bazaar_item_handler(item: String) -> Result<impl Reply, Rejection> {
+#[utoipa::path(post, path = "/bazaar/track", tag = "bazaar", security(("bearer" = ["manage_tracking"])),
+    request_body(content = String, description = "Bazaar product id, as a JSON string"),
+    responses((status = 200, body = Object), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn track_bazaar_item_handler(_user: Uuid, item: String) -> Result<impl Reply, Rejection> {
+    TRACKING.lock().unwrap().insert(item.clone());
     let response = serde_json::json!({
         "message": format!("Now tracking '{}' in bazaar", item),
         "tracked_since": Utc::now()
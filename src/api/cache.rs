@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{LazyLock, Mutex};
+use chrono::{DateTime, Utc};
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use log::error;
+use warp::http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, LAST_MODIFIED};
+use warp::http::{HeaderValue, StatusCode};
+use warp::path::FullPath;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+use crate::api::error::ApiError;
+use crate::api::stream::Streamed;
+use crate::snapshots::{Snapshot, Version, SNAPSHOTS};
+
+/// Queries are free-form, so only this many responses are kept.
+const MAX_ENTRIES: usize = 512;
+
+static RESPONSE_CACHE: LazyLock<ResponseCache> = LazyLock::new(ResponseCache::default);
+
+/// A rendered `200 OK`; only its body and content type are kept.
+#[derive(Debug, Clone)]
+struct Entry {
+    version: Version,
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+}
+
+/// Responses by snapshot and request path with query.
+#[derive(Default)]
+struct ResponseCache {
+    entries: Mutex<HashMap<(Snapshot, String), Entry>>,
+}
+
+impl ResponseCache {
+    /// The cached response, if it was built from the current version of `snapshot`.
+    fn get(&self, snapshot: Snapshot, key: &str, now: DateTime<Utc>) -> Option<Entry> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(&(snapshot, key.to_string()))?;
+        let current = entry.version == SNAPSHOTS.version(snapshot, now) && now < entry.version.expires;
+        current.then(|| entry.clone())
+    }
+
+    fn insert(&self, snapshot: Snapshot, key: String, entry: Entry, now: DateTime<Utc>) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| now < entry.version.expires);
+        }
+        if entries.len() < MAX_ENTRIES {
+            entries.insert((snapshot, key), entry);
+        }
+    }
+}
+
+/// `If-None-Match` and `If-Modified-Since` of a request.
+#[derive(Debug, Clone)]
+struct Conditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+}
+
+impl Conditions {
+    /// `If-None-Match` wins over `If-Modified-Since` when both are sent. Tags are compared
+    /// weakly, as they should be for a GET.
+    fn not_modified(&self, version: &Version) -> bool {
+        if let Some(tags) = &self.if_none_match {
+            let etag = version.etag.trim_start_matches("W/");
+            return tags.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == etag);
+        }
+        self.if_modified_since.as_deref()
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .is_some_and(|since| version.modified.timestamp() <= since.timestamp())
+    }
+}
+
+fn conditions() -> impl Filter<Extract = (Conditions,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("if-none-match")
+        .and(warp::header::optional::<String>("if-modified-since"))
+        .map(|if_none_match, if_modified_since| Conditions { if_none_match, if_modified_since })
+}
+
+/// The request path with its query, e.g. `/v1/calendar/events?limit=10`.
+fn cache_key() -> impl Filter<Extract = (String,), Error = Infallible> + Clone {
+    warp::path::full()
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .map(|path: FullPath, query: String| match query.is_empty() {
+            true => path.as_str().to_string(),
+            false => format!("{}?{}", path.as_str(), query),
+        })
+}
+
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+impl Entry {
+    fn respond(&self, conditions: &Conditions, now: DateTime<Utc>) -> Response {
+        let mut response = if conditions.not_modified(&self.version) {
+            let mut response = Response::new(Bytes::new().into());
+            *response.status_mut() = StatusCode::NOT_MODIFIED;
+            response
+        } else {
+            let mut response = Response::new(self.body.clone().into());
+            if let Some(content_type) = &self.content_type {
+                response.headers_mut().insert(CONTENT_TYPE, content_type.clone());
+            }
+            response
+        };
+        let max_age = (self.version.expires - now).num_seconds().max(0);
+        let headers = response.headers_mut();
+        if let Ok(etag) = HeaderValue::from_str(&self.version.etag) {
+            headers.insert(ETAG, etag);
+        }
+        if let Ok(modified) = HeaderValue::from_str(&http_date(self.version.modified)) {
+            headers.insert(LAST_MODIFIED, modified);
+        }
+        if let Ok(cache_control) = HeaderValue::from_str(&format!("public, max-age={}", max_age)) {
+            headers.insert(CACHE_CONTROL, cache_control);
+        }
+        response
+    }
+}
+
+/// Serves GETs of `routes` from the response cache while `snapshot` is unchanged, and answers
+/// conditional requests with `304 Not Modified`. Streamed responses and responses other than
+/// `200 OK` pass through.
+pub fn cached<F, R>(snapshot: Snapshot, routes: F) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let hit = warp::get()
+        .and(cache_key())
+        .and(conditions())
+        .and_then(move |key: String, conditions: Conditions| async move {
+            let now = Utc::now();
+            RESPONSE_CACHE.get(snapshot, &key, now)
+                .map(|entry| entry.respond(&conditions, now))
+                .ok_or_else(warp::reject::not_found)
+        });
+
+    let miss = warp::get()
+        .and(cache_key())
+        .and(conditions())
+        .and(warp::any().map(move || SNAPSHOTS.version(snapshot, Utc::now())))
+        .and(routes)
+        .then(move |key: String, conditions: Conditions, before: Version, reply: R| {
+            store(snapshot, key, conditions, before, reply.into_response())
+        });
+
+    hit.or(miss).unify()
+}
+
+async fn store(snapshot: Snapshot, key: String, conditions: Conditions, before: Version, response: Response) -> Response {
+    if response.status() != StatusCode::OK || Streamed::is_streamed(&response) {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match body.collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(e) => {
+            error!("Failed to read a response for the cache: {}", e);
+            return ApiError::Internal.into_response();
+        }
+    };
+
+    let now = Utc::now();
+    let current = SNAPSHOTS.version(snapshot, now);
+    // Calendars are changed by other requests, so a response is labelled with the version
+    // it started from. Market data is fetched by the handler and labelled with what it got.
+    let version = match snapshot {
+        Snapshot::Calendar => before,
+        Snapshot::Bazaar | Snapshot::Auctions => current.clone(),
+    };
+    let entry = Entry { version, content_type: parts.headers.get(CONTENT_TYPE).cloned(), body };
+    if entry.version == current {
+        RESPONSE_CACHE.insert(snapshot, key, entry.clone(), now);
+    }
+    entry.respond(&conditions, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use super::*;
+
+    fn version(now: DateTime<Utc>) -> Version {
+        Version { etag: "W/\"bazaar-3\"".to_string(), modified: now - Duration::seconds(30), expires: now + Duration::seconds(30) }
+    }
+
+    fn conditions(if_none_match: Option<&str>, if_modified_since: Option<String>) -> Conditions {
+        Conditions { if_none_match: if_none_match.map(str::to_string), if_modified_since }
+    }
+
+    #[test]
+    fn entity_tags_match_weakly() {
+        let version = version(Utc::now());
+        assert!(conditions(Some("W/\"bazaar-3\""), None).not_modified(&version));
+        assert!(conditions(Some("\"bazaar-3\""), None).not_modified(&version));
+        assert!(conditions(Some("\"bazaar-2\", W/\"bazaar-3\""), None).not_modified(&version));
+        assert!(conditions(Some("*"), None).not_modified(&version));
+        assert!(!conditions(Some("W/\"bazaar-2\""), None).not_modified(&version));
+        assert!(!conditions(None, None).not_modified(&version));
+    }
+
+    #[test]
+    fn modified_since_is_compared_to_the_second() {
+        let now = Utc::now();
+        let version = version(now);
+        assert!(conditions(None, Some(http_date(version.modified))).not_modified(&version));
+        assert!(conditions(None, Some(http_date(now))).not_modified(&version));
+        assert!(!conditions(None, Some(http_date(version.modified - Duration::seconds(1)))).not_modified(&version));
+        assert!(!conditions(None, Some("yesterday".to_string())).not_modified(&version));
+        // A tag that doesn't match wins over a date that would.
+        assert!(!conditions(Some("W/\"bazaar-2\""), Some(http_date(now))).not_modified(&version));
+    }
+
+    #[test]
+    fn entries_answer_conditional_requests() {
+        let now = Utc::now();
+        let entry = Entry {
+            version: version(now),
+            content_type: Some(HeaderValue::from_static("application/json")),
+            body: Bytes::from_static(b"[]"),
+        };
+
+        let response = entry.respond(&conditions(None, None), now);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], "W/\"bazaar-3\"");
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=30");
+        assert_eq!(response.headers()[LAST_MODIFIED], http_date(entry.version.modified).as_str());
+
+        let response = entry.respond(&conditions(Some("W/\"bazaar-3\""), None), now);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], "W/\"bazaar-3\"");
+        assert!(!response.headers().contains_key(CONTENT_TYPE));
+
+        let response = entry.respond(&conditions(None, None), now + Duration::seconds(60));
+        assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=0");
+    }
+}
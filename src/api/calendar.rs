@@ -1,59 +1,207 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
 use warp::{Filter, Rejection, Reply};
-use crate::calendar::calendar::Event;
+use crate::api::cache::cached;
+use crate::api::error::{ApiError, ErrorBody};
+use crate::calendar::calendar::{Calendar, CalendarSummary, Category, Event, EventPage, EventQuery, SortOrder};
+use crate::calendar::database::DATABASE;
+use crate::calendar::skyblock;
+use crate::snapshots::Snapshot;
 
-pub fn calendar_routes() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+pub fn calendar_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     let calendar_path = warp::path("calendar");
 
-    // GET /calendar/upcoming
+    // GET /calendar/public
+    let public = calendar_path
+        .and(warp::path("public"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then(public_calendars_handler);
+
+    // GET /calendar/upcoming?tz=
     let upcoming = calendar_path
         .and(warp::path("upcoming"))
+        .and(warp::path::end())
         .and(warp::get())
-        .and_then(upcoming_events_handler);
+        .and(warp::query::<TimezoneQuery>())
+        .then(upcoming_events_handler);
 
-    // GET /calendar/events
-    let events = calendar_path
+    // GET /calendar/events?from=&to=&q=&tag=&category=&ongoing=&limit=&cursor=&order=asc|desc&tz=
+    let events_query = calendar_path
         .and(warp::path("events"))
+        .and(warp::path::end())
         .and(warp::get())
-        .and_then(all_events_handler);
+        .and(warp::query::<EventsQuery>());
+    // Without `from` or with `ongoing` the answer depends on the time, so only the others are cached.
+    let events = cached(Snapshot::Calendar, events_query
+        .and_then(|params: EventsQuery| async move {
+            match params.is_fixed() {
+                true => Ok(params),
+                false => Err(warp::reject::not_found()),
+            }
+        })
+        .then(events_handler))
+        .or(events_query.then(events_handler));
 
+    // GET /calendar/events/{id}?tz=
+    let event = calendar_path
+        .and(warp::path("events"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<TimezoneQuery>())
+        .then(event_handler);
 
-    upcoming
+
+    public
+        .or(upcoming)
         .or(events)
+        .or(event)
+}
+
+/// Public calendars, without their events.
+#[utoipa::path(get, path = "/calendar/public", tag = "calendar",
+    responses((status = 200, body = Vec<CalendarSummary>)))]
+async fn public_calendars_handler() -> Result<impl Reply, Rejection> {
+    let calendars: Vec<CalendarSummary> = DATABASE.read().unwrap()
+        .public_calendars()
+        .into_iter()
+        .map(Calendar::summary)
+        .collect();
+    Ok(warp::reply::json(&calendars))
+}
+
+/// Accepts IANA timezone names such as `Europe/Berlin`.
+pub(crate) fn parse_timezone(value: &str) -> Result<Tz, String> {
+    value.parse::<Tz>()
+        .map_err(|_| format!("Unknown timezone '{}', expected an IANA name like Europe/Berlin", value))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct TimezoneQuery {
+    /// IANA timezone to add local times in, e.g. `Europe/Berlin`.
+    tz: Option<String>,
+}
+
+impl TimezoneQuery {
+    fn timezone(&self) -> Result<Option<Tz>, ApiError> {
+        self.tz.as_deref().map(parse_timezone).transpose().map_err(ApiError::BadRequest)
+    }
 }
 
-async fn upcoming_events_handler() -> Result<impl Reply, Rejection> {
+/// Upcoming events of all public calendars, soonest first.
+#[utoipa::path(get, path = "/calendar/upcoming", tag = "calendar", params(TimezoneQuery),
+    responses((status = 200, body = Vec<Event>), (status = 400, body = ErrorBody)))]
+async fn upcoming_events_handler(params: TimezoneQuery) -> Result<impl Reply, ApiError> {
+    let timezone = params.timezone()?;
     let now = Utc::now();
-    let upcoming_events = vec![
-        Event::new(
-            "event name".to_string(),
-            "".to_string(),
-            now,
-            now,
-            now,
-            10i64,
-            10i64,
-            10i64            
-        )
-    ];
+    let database = DATABASE.read().unwrap();
+    let mut upcoming_events: Vec<Event> = database.public_calendars()
+        .into_iter()
+        .flat_map(|calendar| calendar.find_upcoming_events(now))
+        .map(|event| match timezone {
+            Some(timezone) => event.clone().localized(timezone),
+            None => event.clone(),
+        })
+        .collect();
+    upcoming_events.sort_by_key(|event| event.start_time);
 
     Ok(warp::reply::json(&upcoming_events))
 }
 
-async fn all_events_handler() -> Result<impl Reply, Rejection> {
-    let now = Utc::now();
-    let events = vec![
-        Event::new(
-            "event name".to_string(),
-            "".to_string(),
-            now,
-            now,
-            now,
-            10i64,
-            10i64,
-            10i64
-        )
-    ];
-
-    Ok(warp::reply::json(&events))
+const DEFAULT_EVENT_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct EventsQuery {
+    /// RFC 3339 or unix seconds.
+    from: Option<String>,
+    /// RFC 3339 or unix seconds, at most 62 days after `from`. Defaults to 7 days after `from`.
+    to: Option<String>,
+    /// Case-insensitive substring of the title.
+    q: Option<String>,
+    tag: Option<String>,
+    category: Option<Category>,
+    /// Only events running right now.
+    #[serde(default)]
+    ongoing: bool,
+    #[param(default = 100)]
+    limit: Option<usize>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+    #[serde(default)]
+    order: SortOrder,
+    /// IANA timezone to add local times in, e.g. `Europe/Berlin`.
+    tz: Option<String>,
+}
+
+/// Accepts RFC 3339 timestamps or unix seconds.
+pub(crate) fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    value.parse::<i64>().ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .or_else(|| DateTime::parse_from_rfc3339(value).ok().map(|date| date.with_timezone(&Utc)))
+        .ok_or_else(|| format!("Invalid time '{}', expected RFC 3339 or unix seconds", value))
+}
+
+impl EventsQuery {
+    /// Whether the answer stays the same until the calendar changes.
+    fn is_fixed(&self) -> bool {
+        self.from.is_some() && !self.ongoing
+    }
+
+    pub(crate) fn into_query(self) -> Result<EventQuery, String> {
+        let query = EventQuery {
+            from: self.from.as_deref().map(parse_time).transpose()?,
+            to: self.to.as_deref().map(parse_time).transpose()?,
+            title: self.q.filter(|q| !q.is_empty()),
+            tag: self.tag.filter(|tag| !tag.is_empty()),
+            category: self.category,
+            ongoing: self.ongoing,
+            limit: self.limit.unwrap_or(DEFAULT_EVENT_LIMIT),
+            cursor: self.cursor.filter(|cursor| !cursor.is_empty()),
+            order: self.order,
+            timezone: self.tz.as_deref().map(parse_timezone).transpose()?,
+            ..EventQuery::default()
+        };
+        query.validate()?;
+        Ok(query)
+    }
+}
+
+/// Occurrences of public events, paged.
+#[utoipa::path(get, path = "/calendar/events", tag = "calendar", params(EventsQuery),
+    responses((status = 200, body = EventPage), (status = 304, description = "Not modified"), (status = 400, body = ErrorBody)))]
+async fn events_handler(params: EventsQuery) -> Result<impl Reply, ApiError> {
+    let query = params.into_query().map_err(ApiError::BadRequest)?;
+
+    let database = DATABASE.read().unwrap();
+    let occurrences = database.public_calendars()
+        .into_iter()
+        .flat_map(|calendar| database.occurrences(calendar, &query))
+        .collect();
+    Ok(warp::reply::json(&query.page(occurrences)))
+}
+
+#[utoipa::path(get, path = "/calendar/events/{id}", tag = "calendar",
+    params(("id" = Uuid, Path, description = "Event id"), TimezoneQuery),
+    responses((status = 200, body = Event), (status = 400, body = ErrorBody), (status = 404, body = ErrorBody)))]
+async fn event_handler(id: Uuid, params: TimezoneQuery) -> Result<impl Reply, ApiError> {
+    let timezone = params.timezone()?;
+    let stored = DATABASE.read().unwrap()
+        .public_calendars()
+        .into_iter()
+        .find_map(|calendar| calendar.get_event(id).cloned());
+    // Only the next few days of the Skyblock calendar are stored, but ids from range queries
+    // may name events from any Skyblock year.
+    let event = stored
+        .or_else(|| skyblock::find_event(id))
+        .ok_or_else(|| ApiError::NotFound(format!("No event with id {}", id)))?;
+    match timezone {
+        Some(timezone) => Ok(warp::reply::json(&event.localized(timezone))),
+        None => Ok(warp::reply::json(&event)),
+    }
 }
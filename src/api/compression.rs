@@ -0,0 +1,244 @@
+use std::convert::Infallible;
+use std::io::{self, Write};
+use futures_util::StreamExt;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use log::error;
+use warp::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY};
+use warp::http::{HeaderMap, HeaderValue, StatusCode};
+use warp::reply::Response;
+use warp::{Filter, Reply};
+use crate::api::error::ApiError;
+use crate::api::stream::{ByteStream, Streamed};
+
+/// Smaller bodies aren't worth the CPU, and may grow when compressed.
+const MIN_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Zstd,
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// Ours first when a client likes several equally.
+    const PREFERENCE: [Encoding; 3] = [Encoding::Zstd, Encoding::Brotli, Encoding::Gzip];
+
+    fn token(self) -> &'static str {
+        match self {
+            Encoding::Zstd => "zstd",
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn encoder(self) -> io::Result<Encoder> {
+        Ok(match self {
+            Encoding::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(Vec::new(), 3)?),
+            // Quality 11 is far too slow for multi-megabyte responses.
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22))),
+            Encoding::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default())),
+        })
+    }
+}
+
+/// The best encoding the `Accept-Encoding` header allows, by q-value and then our preference.
+pub fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+    let mut wildcard = None;
+    let mut weights: Vec<(Encoding, f32)> = Vec::new();
+    for value in headers.get_all(ACCEPT_ENCODING).iter().filter_map(|value| value.to_str().ok()) {
+        for item in value.split(',') {
+            let mut parts = item.split(';');
+            let token = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if token == "*" {
+                wildcard = Some(q);
+            } else if let Some(encoding) = Encoding::PREFERENCE.into_iter().find(|encoding| encoding.token() == token) {
+                weights.push((encoding, q));
+            }
+        }
+    }
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in Encoding::PREFERENCE {
+        let q = weights.iter().find(|(named, _)| *named == encoding).map(|(_, q)| *q).or(wildcard).unwrap_or(0.0);
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+enum Encoder {
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    /// Compresses `chunk` and returns whatever output is ready so far.
+    fn write(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        let output = match self {
+            Encoder::Zstd(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.get_mut()
+            }
+            Encoder::Brotli(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.get_mut()
+            }
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.get_mut()
+            }
+        };
+        Ok(Bytes::from(std::mem::take(output)))
+    }
+
+    fn finish(self) -> io::Result<Bytes> {
+        let output = match self {
+            Encoder::Zstd(encoder) => encoder.finish()?,
+            Encoder::Brotli(encoder) => encoder.into_inner(),
+            Encoder::Gzip(encoder) => encoder.finish()?,
+        };
+        Ok(Bytes::from(output))
+    }
+}
+
+fn compress_stream(encoder: Encoder, stream: ByteStream) -> ByteStream {
+    Box::pin(futures_util::stream::unfold(Some((encoder, stream)), |state| async move {
+        let (mut encoder, mut stream) = state?;
+        loop {
+            let output = match stream.next().await {
+                Some(Ok(chunk)) => encoder.write(&chunk),
+                Some(Err(e)) => return Some((Err(e), None)),
+                None => return Some((encoder.finish(), None)),
+            };
+            match output {
+                // The encoder is still filling its buffer.
+                Ok(output) if output.is_empty() => continue,
+                Ok(output) => return Some((Ok(output), Some((encoder, stream)))),
+                Err(e) => return Some((Err(e), None)),
+            }
+        }
+    }))
+}
+
+/// Whether a response should be compressed at all.
+fn compressible(response: &Response) -> bool {
+    let content_type = response.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    response.status() != StatusCode::NO_CONTENT
+        && response.status() != StatusCode::NOT_MODIFIED
+        && !response.headers().contains_key(CONTENT_ENCODING)
+        && (content_type.starts_with("application/json")
+            || content_type.starts_with("application/x-ndjson")
+            || content_type.starts_with("text/"))
+}
+
+/// Compresses `reply` with `encoding`. Streamed bodies are compressed as they are sent.
+async fn compress(encoding: Option<Encoding>, reply: impl Reply) -> Response {
+    let mut response = reply.into_response();
+    if !compressible(&response) {
+        return response;
+    }
+    // Caches in between must not hand a compressed body to a client that can't read it.
+    response.headers_mut().append(VARY, HeaderValue::from_static("accept-encoding"));
+    let Some(encoding) = encoding else {
+        return response;
+    };
+    let mut encoder = match encoding.encoder() {
+        Ok(encoder) => encoder,
+        Err(e) => {
+            error!("Failed to start {} compression: {}", encoding.token(), e);
+            return response;
+        }
+    };
+
+    if let Some(stream) = Streamed::take(&mut response) {
+        Streamed::attach(&mut response, compress_stream(encoder, stream));
+    } else {
+        let (parts, body) = response.into_parts();
+        let body = match body.collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(e) => {
+                error!("Failed to read a response to compress: {}", e);
+                return ApiError::Internal.into_response();
+            }
+        };
+        if body.len() < MIN_SIZE {
+            return Response::from_parts(parts, body.into());
+        }
+        let compressed = match encoder.write(&body).and_then(|head| Ok([head, encoder.finish()?].concat())) {
+            Ok(compressed) => compressed,
+            Err(e) => {
+                error!("Failed to compress a response with {}: {}", encoding.token(), e);
+                return Response::from_parts(parts, body.into());
+            }
+        };
+        response = Response::from_parts(parts, compressed.into());
+    }
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.token()));
+    headers.remove(CONTENT_LENGTH);
+    response
+}
+
+/// Compresses replies of `routes` with the best encoding the client accepts.
+pub fn compressed<F, R>(routes: F) -> impl Filter<Extract = (Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (R,), Error = Infallible> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    warp::header::headers_cloned()
+        .and(routes)
+        .then(|headers: HeaderMap, reply: R| compress(negotiate(&headers), reply))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use super::*;
+
+    fn accepting(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(ACCEPT_ENCODING, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn negotiation_follows_q_values_then_preference() {
+        assert_eq!(negotiate(&accepting(&[])), None);
+        assert_eq!(negotiate(&accepting(&["identity"])), None);
+        assert_eq!(negotiate(&accepting(&["gzip, br"])), Some(Encoding::Brotli));
+        assert_eq!(negotiate(&accepting(&["gzip;q=1.0, zstd;q=0.5"])), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&accepting(&["GZIP", "br;q=0.8"])), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&accepting(&["*"])), Some(Encoding::Zstd));
+        assert_eq!(negotiate(&accepting(&["*;q=0.5, zstd;q=0, br;q=0"])), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&accepting(&["gzip;q=0"])), None);
+    }
+
+    #[tokio::test]
+    async fn large_json_is_compressed_and_small_left_alone() {
+        let items: Vec<u32> = (0..1000).collect();
+        let response = compress(Some(Encoding::Gzip), warp::reply::json(&items)).await;
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[VARY], "accept-encoding");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(&body[..]).read_to_string(&mut json).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<u32>>(&json).unwrap(), items);
+
+        let response = compress(Some(Encoding::Gzip), warp::reply::json(&[1, 2, 3])).await;
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+        assert_eq!(response.headers()[VARY], "accept-encoding");
+
+        let response = compress(Some(Encoding::Gzip), warp::reply::with_status(warp::reply(), StatusCode::NO_CONTENT)).await;
+        assert!(!response.headers().contains_key(VARY));
+    }
+}
@@ -0,0 +1,286 @@
+use std::sync::LazyLock;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use warp::{Filter, Rejection, Reply};
+use crate::api::{admin, auctions, auth, bazaar, calendar, error, health, metrics, skyblock, users};
+
+/// Swagger UI, loaded from a CDN, pointed at `/openapi.json`.
+const DOCS_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Skyblock API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="docs"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => SwaggerUIBundle({ url: "/openapi.json", dom_id: "#docs" });
+  </script>
+</body>
+</html>
+"##;
+
+/// Documented paths, e.g. `/v1/calendar/events/{id}`, which requests are labelled with.
+static ROUTE_TEMPLATES: LazyLock<Vec<String>> = LazyLock::new(|| ApiDoc::openapi().paths.paths.into_keys().collect());
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Skyblock API", description = "Hypixel Skyblock calendar and market API"),
+    paths(
+        openapi_handler,
+        docs_handler,
+        health::health_handler,
+        health::live_handler,
+        health::ready_handler,
+        metrics::metrics_handler,
+        admin::refresh_handler,
+        admin::regenerate_handler,
+        admin::database_handler,
+        admin::edit_calendar_handler,
+        admin::remove_user_handler,
+        admin::log_handler,
+        admin::set_log_handler,
+        admin::snapshot_handler,
+        admin::replay_handler,
+    ),
+    nest((path = "/v1", api = V1Doc)),
+    components(schemas(error::ErrorBody)),
+    modifiers(&BearerAuth),
+)]
+pub struct ApiDoc;
+
+/// Routes served under `/v1`.
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        auth::register_handler,
+        auth::issue_token_handler,
+        auth::revoke_token_handler,
+        bazaar::list_bazaar_handler,
+        bazaar::track_bazaar_item_handler,
+        auctions::list_auctions_handler,
+        auctions::auction_snapshot_handler,
+        auctions::track_auction_handler,
+        auctions::lowest_bin_handler,
+        calendar::public_calendars_handler,
+        calendar::upcoming_events_handler,
+        calendar::events_handler,
+        calendar::event_handler,
+        users::calendars_handler,
+        users::create_calendar_handler,
+        users::create_event_handler,
+        users::share_handler,
+        users::unshare_handler,
+        users::subscriptions_handler,
+        users::subscribe_handler,
+        users::unsubscribe_handler,
+        users::preferences_handler,
+        users::set_preferences_handler,
+        users::agenda_handler,
+        users::agenda_view_handler,
+        users::free_busy_handler,
+        users::free_slot_handler,
+        skyblock::jacob_contests_handler,
+        skyblock::import_jacob_contests_handler,
+        skyblock::current_election_handler,
+        skyblock::election_history_handler,
+        skyblock::election_year_handler,
+    ),
+    components(schemas(crate::auth::Scope, crate::calendar::calendar::Calendar)),
+)]
+struct V1Doc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("bearer", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+fn segments(path: &str) -> Vec<&str> {
+    path.split('/').filter(|segment| !segment.is_empty()).collect()
+}
+
+fn matches_template(template: &str, path: &str) -> bool {
+    let (template, path) = (segments(template), segments(path));
+    template.len() == path.len()
+        && template.iter().zip(&path).all(|(expected, segment)| expected.starts_with('{') || expected == segment)
+}
+
+/// The documented path `path` is served at, with parameters in braces. The deprecated root
+/// copies of `/v1` routes get their own template without the prefix.
+pub(crate) fn route_template(path: &str) -> Option<String> {
+    let find = |path: &str| ROUTE_TEMPLATES.iter().find(|template| matches_template(template, path));
+    if let Some(template) = find(path) {
+        return Some(template.clone());
+    }
+    find(&format!("/v1{}", path)).map(|template| template.trim_start_matches("/v1").to_string())
+}
+
+pub fn docs_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    // GET /openapi.json
+    let spec = warp::path("openapi.json")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(openapi_handler);
+
+    // GET /docs
+    let docs = warp::path("docs")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(docs_handler);
+
+    spec.or(docs)
+}
+
+/// This document.
+#[utoipa::path(get, path = "/openapi.json", tag = "docs", responses((status = 200, body = Object)))]
+fn openapi_handler() -> warp::reply::Json {
+    warp::reply::json(&ApiDoc::openapi())
+}
+
+/// Interactive documentation.
+#[utoipa::path(get, path = "/docs", tag = "docs", responses((status = 200, content_type = "text/html", body = String)))]
+fn docs_handler() -> warp::reply::Html<&'static str> {
+    warp::reply::html(DOCS_PAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use utoipa::openapi::PathItem;
+    use utoipa::OpenApi;
+    use warp::http::StatusCode;
+    use warp::{Filter, Reply};
+    use crate::api::build_routes;
+    use crate::server::RemoteAddr;
+    use super::{matches_template, route_template, ApiDoc};
+
+    const METHODS: [&str; 4] = ["GET", "POST", "PUT", "DELETE"];
+
+    /// Stand-ins for path parameters: ids, and years for `/skyblock/election/{year}`.
+    const SAMPLE_PARAMS: [&str; 2] = ["1f0e8a46-4e2e-4bb7-9a43-2d43c3a5d0b1", "402"];
+
+    fn has_operation(item: &PathItem, method: &str) -> bool {
+        match method {
+            "GET" => item.get.is_some(),
+            "POST" => item.post.is_some(),
+            "PUT" => item.put.is_some(),
+            "DELETE" => item.delete.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Every literal segment the route filters in `src/api` match.
+    fn path_literals() -> BTreeSet<String> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/api");
+        let mut literals = BTreeSet::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let source = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            for (at, call) in source.match_indices("warp::path(\"") {
+                let rest = &source[at + call.len()..];
+                literals.extend(rest.split_once('"').map(|(literal, _)| literal.to_string()));
+            }
+        }
+        literals
+    }
+
+    /// `template` with every combination of sample values for its parameters.
+    fn samples(template: &str) -> Vec<String> {
+        template.split('/').skip(1).fold(vec![String::new()], |paths, segment| {
+            let choices: Vec<&str> = if segment.starts_with('{') { SAMPLE_PARAMS.to_vec() } else { vec![segment] };
+            paths.iter().flat_map(|path| choices.iter().map(move |choice| format!("{}/{}", path, choice))).collect()
+        })
+    }
+
+    /// What the full filter tree answers `method path` with, `None` if no route has the path.
+    /// Every request comes from its own address, so rate limits never answer instead.
+    async fn probe<F>(routes: &F, method: &str, path: &str) -> Option<StatusCode>
+    where
+        F: Filter + 'static,
+        F::Extract: Reply + Send,
+    {
+        static CLIENTS: AtomicU32 = AtomicU32::new(1);
+        let client = SocketAddr::from((Ipv4Addr::from(CLIENTS.fetch_add(1, Ordering::Relaxed)), 443));
+        let response = warp::test::request()
+            .method(method)
+            .path(path)
+            .extension(RemoteAddr(client))
+            .reply(routes)
+            .await;
+        let unrouted = response.status() == StatusCode::NOT_FOUND
+            && String::from_utf8_lossy(response.body()).contains("No such route");
+        (!unrouted).then_some(response.status())
+    }
+
+    /// Probes every path one segment past a documented one, and every two-segment path, made
+    /// of the literals the route filters match and sample parameters. Each path with a route
+    /// must be documented for every method it answers, and each documented route must exist.
+    #[tokio::test]
+    async fn every_route_is_documented() {
+        let spec = ApiDoc::openapi();
+        let routes = build_routes();
+        let documented = |method: &str, path: &str| {
+            spec.paths.paths.iter()
+                .filter(|(template, _)| matches_template(template, path) || matches_template(template, &format!("/v1{}", path)))
+                .any(|(_, item)| has_operation(item, method))
+        };
+
+        let mut segments = path_literals();
+        assert!(segments.len() > 20, "found only {} path literals", segments.len());
+        segments.extend(SAMPLE_PARAMS.iter().map(|sample| sample.to_string()));
+
+        let mut prefixes = BTreeSet::from([String::new()]);
+        for template in spec.paths.paths.keys() {
+            let mut routed = false;
+            for path in samples(template) {
+                routed |= probe(&routes, "PATCH", &path).await.is_some();
+                let mut prefix = String::new();
+                for segment in path.split('/').skip(1) {
+                    prefix = format!("{}/{}", prefix, segment);
+                    prefixes.insert(prefix.clone());
+                }
+            }
+            assert!(routed, "{} is documented but has no route", template);
+        }
+        let mut candidates = prefixes.clone();
+        for prefix in &prefixes {
+            candidates.extend(segments.iter().map(|segment| format!("{}/{}", prefix, segment)));
+        }
+        for first in &segments {
+            candidates.extend(segments.iter().map(|second| format!("/{}/{}", first, second)));
+        }
+
+        // Routes reject other methods with 405, so paths without any route are the ones
+        // a PATCH gets a 404 for. Documented operations are not requested.
+        let mut undocumented = Vec::new();
+        for path in candidates.iter().filter(|path| !path.is_empty()) {
+            if probe(&routes, "PATCH", path).await.is_none() {
+                continue;
+            }
+            for method in METHODS.iter().filter(|method| !documented(method, path)) {
+                if probe(&routes, method, path).await.is_some_and(|status| status != StatusCode::METHOD_NOT_ALLOWED) {
+                    undocumented.push(format!("{} {}", method, path));
+                }
+            }
+        }
+        assert!(undocumented.is_empty(), "routes without an OpenAPI entry: {:?}", undocumented);
+    }
+
+    #[test]
+    fn requests_are_labelled_by_route() {
+        let id = "1f0e8a46-4e2e-4bb7-9a43-2d43c3a5d0b1";
+        assert_eq!(route_template(&format!("/v1/calendar/events/{}", id)).as_deref(), Some("/v1/calendar/events/{id}"));
+        assert_eq!(route_template(&format!("/calendar/events/{}", id)).as_deref(), Some("/calendar/events/{id}"));
+        assert_eq!(route_template("/health/ready/").as_deref(), Some("/health/ready"));
+        assert_eq!(route_template("/calendar/public/anything"), None);
+        assert_eq!(route_template("/wp-login.php"), None);
+    }
+}
@@ -0,0 +1,201 @@
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use log::error;
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+use warp::body::BodyDeserializeError;
+use warp::http::StatusCode;
+use warp::reject::{
+    InvalidHeader, InvalidQuery, LengthRequired, MethodNotAllowed, MissingHeader, PayloadTooLarge, Reject,
+    UnsupportedMediaType,
+};
+use warp::{Rejection, Reply};
+use crate::api::rate_limit;
+use crate::auth::Scope;
+use crate::calendar::database::DatabaseError;
+use crate::logger::current_request_id;
+
+/// Every error the API returns, serialized as an [`ErrorBody`].
+#[derive(Debug, Clone)]
+pub enum ApiError {
+    /// Malformed or out of range parameters.
+    BadRequest(String),
+    /// A JSON body that could not be parsed.
+    InvalidBody(String),
+    Unauthorized(&'static str),
+    MissingScope(Scope),
+    Forbidden(String),
+    NotFound(String),
+    MethodNotAllowed,
+    LengthRequired,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    RateLimited { retry_after: u64 },
+    /// Hypixel failed or could not be reached.
+    Upstream(String),
+    /// A data file could not be written.
+    Storage(String),
+    Internal,
+}
+
+impl Reject for ApiError {}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Human readable message.
+    #[schema(example = "No event with id 00000000-0000-0000-0000-000000000000")]
+    error: String,
+    /// Stable name of the error, e.g. `not_found` or `rate_limited`.
+    #[schema(example = "not_found")]
+    code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Value>,
+    /// Also sent as the `X-Request-Id` header.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) | ApiError::InvalidBody(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::MissingScope(_) | ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::LengthRequired => StatusCode::LENGTH_REQUIRED,
+            ApiError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Storage(_) | ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable, machine readable name of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::InvalidBody(_) => "invalid_body",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::MissingScope(_) => "missing_scope",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::MethodNotAllowed => "method_not_allowed",
+            ApiError::LengthRequired => "length_required",
+            ApiError::PayloadTooLarge => "payload_too_large",
+            ApiError::UnsupportedMediaType => "unsupported_media_type",
+            ApiError::RateLimited { .. } => "rate_limited",
+            ApiError::Upstream(_) => "upstream_error",
+            ApiError::Storage(_) => "storage_error",
+            ApiError::Internal => "internal_error",
+        }
+    }
+
+    fn details(&self) -> Option<Value> {
+        match self {
+            ApiError::MissingScope(scope) => Some(serde_json::json!({ "scope": scope })),
+            ApiError::RateLimited { retry_after } => Some(serde_json::json!({ "retry_after": retry_after })),
+            ApiError::Upstream(_) => Some(serde_json::json!({ "service": "hypixel" })),
+            _ => None,
+        }
+    }
+
+    pub fn reject(self) -> Rejection {
+        warp::reject::custom(self)
+    }
+
+    pub fn upstream(e: reqwest::Error) -> Self {
+        error!("Hypixel request failed: {}", e);
+        let message = match e.status() {
+            Some(status) => format!("Hypixel answered with {}", status),
+            None if e.is_decode() => "Hypixel sent an unexpected response".to_string(),
+            None => "Hypixel could not be reached".to_string(),
+        };
+        ApiError::Upstream(message)
+    }
+
+    pub fn storage(e: serde_json::Error) -> Self {
+        error!("Failed to save data: {}", e);
+        ApiError::Storage("The change could not be saved".to_string())
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::InvalidBody(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Upstream(message)
+            | ApiError::Storage(message) => write!(f, "{}", message),
+            ApiError::Unauthorized(message) => write!(f, "{}", message),
+            ApiError::MissingScope(scope) => write!(f, "This token lacks the '{}' scope", scope),
+            ApiError::MethodNotAllowed => write!(f, "Method not allowed"),
+            ApiError::LengthRequired => write!(f, "A Content-Length header is required"),
+            ApiError::PayloadTooLarge => write!(f, "The request body is too large"),
+            ApiError::UnsupportedMediaType => write!(f, "The request body must be JSON"),
+            ApiError::RateLimited { retry_after } => write!(f, "Too many requests, retry in {} seconds", retry_after),
+            ApiError::Internal => write!(f, "Internal server error"),
+        }
+    }
+}
+
+impl Reply for ApiError {
+    fn into_response(self) -> warp::reply::Response {
+        let body = ErrorBody {
+            error: self.to_string(),
+            code: self.code(),
+            details: self.details(),
+            request_id: current_request_id(),
+        };
+        warp::reply::with_status(warp::reply::json(&body), self.status()).into_response()
+    }
+}
+
+impl From<DatabaseError> for ApiError {
+    fn from(e: DatabaseError) -> Self {
+        match e {
+            DatabaseError::UserNotFound(_) | DatabaseError::CalendarNotFound(_) => ApiError::NotFound(e.to_string()),
+            DatabaseError::Forbidden(_) => ApiError::Forbidden(e.to_string()),
+            DatabaseError::Invalid(message) => ApiError::BadRequest(message),
+        }
+    }
+}
+
+/// Turns every rejection into an [`ApiError`] response.
+pub async fn handle_rejection(rejection: Rejection) -> Result<warp::reply::Response, Infallible> {
+    if let Some(response) = rate_limit::rejection_response(&rejection) {
+        return Ok(response);
+    }
+
+    let error = if let Some(e) = rejection.find::<ApiError>() {
+        e.clone()
+    } else if rejection.is_not_found() {
+        ApiError::NotFound("No such route".to_string())
+    } else if let Some(e) = rejection.find::<BodyDeserializeError>() {
+        let cause = e.source().map_or_else(|| e.to_string(), ToString::to_string);
+        ApiError::InvalidBody(format!("Invalid JSON body: {}", cause))
+    } else if let Some(e) = rejection.find::<InvalidQuery>() {
+        ApiError::BadRequest(e.to_string())
+    } else if let Some(e) = rejection.find::<MissingHeader>() {
+        ApiError::BadRequest(e.to_string())
+    } else if let Some(e) = rejection.find::<InvalidHeader>() {
+        ApiError::BadRequest(e.to_string())
+    } else if rejection.find::<MethodNotAllowed>().is_some() {
+        ApiError::MethodNotAllowed
+    } else if rejection.find::<LengthRequired>().is_some() {
+        ApiError::LengthRequired
+    } else if rejection.find::<PayloadTooLarge>().is_some() {
+        ApiError::PayloadTooLarge
+    } else if rejection.find::<UnsupportedMediaType>().is_some() {
+        ApiError::UnsupportedMediaType
+    } else {
+        error!("Unhandled rejection: {:?}", rejection);
+        ApiError::Internal
+    };
+    Ok(error.into_response())
+}
@@ -0,0 +1,65 @@
+use chrono::Utc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+use crate::health::HEALTH;
+
+pub fn health_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let health_path = warp::path("health");
+
+    // GET /health
+    let health = health_path
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(health_handler);
+
+    // GET /health/live
+    let live = health_path
+        .and(warp::path("live"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(live_handler);
+
+    // GET /health/ready
+    let ready = health_path
+        .and(warp::path("ready"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(ready_handler);
+
+    health.or(live).unify().or(ready).unify()
+}
+
+/// Same as `/health/live`, kept for existing probes.
+#[utoipa::path(get, path = "/health", tag = "health", responses((status = 200, body = Object)))]
+fn health_handler() -> warp::reply::WithStatus<warp::reply::Json> {
+    live_handler()
+}
+
+/// The process is up and serving requests.
+#[utoipa::path(get, path = "/health/live", tag = "health", responses((status = 200, body = Object)))]
+fn live_handler() -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({
+            "status": "alive",
+            "uptime_secs": HEALTH.uptime_secs(),
+            "timestamp": Utc::now().timestamp()
+        })),
+        StatusCode::OK,
+    )
+}
+
+/// Every critical component works; 503 otherwise, so traffic is routed elsewhere.
+#[utoipa::path(get, path = "/health/ready", tag = "health",
+    responses((status = 200, body = Object), (status = 503, body = Object)))]
+fn ready_handler() -> warp::reply::WithStatus<warp::reply::Json> {
+    let readiness = HEALTH.readiness();
+    let status = if readiness.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({
+            "status": if readiness.ready { "ready" } else { "not_ready" },
+            "checks": readiness.checks,
+            "timestamp": Utc::now().timestamp()
+        })),
+        status,
+    )
+}
@@ -0,0 +1,22 @@
+use warp::http::header::CONTENT_TYPE;
+use warp::{Filter, Rejection, Reply};
+use crate::api::{auctions, bazaar};
+use crate::metrics::METRICS;
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+pub fn metrics_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    // GET /metrics
+    warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(metrics_handler)
+}
+
+/// Prometheus text exposition format.
+#[utoipa::path(get, path = "/metrics", tag = "metrics",
+    responses((status = 200, content_type = "text/plain", body = String)))]
+fn metrics_handler() -> impl Reply {
+    let body = METRICS.render(auctions::tracked_count(), bazaar::tracked_count());
+    warp::reply::with_header(body, CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)
+}
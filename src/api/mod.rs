@@ -1,32 +1,99 @@
-use chrono::Utc;
+use std::convert::Infallible;
+use serde::de::DeserializeOwned;
+use warp::http::header::LINK;
+use warp::http::HeaderValue;
+use warp::path::FullPath;
+use warp::reply::Response;
 use warp::{Filter, Rejection, Reply};
+use crate::api::admin::admin_routes;
 use crate::api::auctions::auctions_routes;
+use crate::api::auth::auth_routes;
 use crate::api::bazaar::bazaar_routes;
 use crate::api::calendar::calendar_routes;
+use crate::api::compression::compressed;
+use crate::api::docs::docs_routes;
+use crate::api::health::health_routes;
+use crate::api::metrics::metrics_routes;
+use crate::api::error::handle_rejection;
+use crate::api::rate_limit::{limited, RouteGroup};
+use crate::api::skyblock::skyblock_routes;
+use crate::api::users::users_routes;
 
+mod admin;
+mod auth;
 mod bazaar;
 mod auctions;
+mod cache;
 mod calendar;
+mod compression;
+pub(crate) mod docs;
+pub(crate) mod error;
 mod auction_items;
+mod health;
+mod metrics;
+pub(crate) mod rate_limit;
+pub(crate) mod stream;
+mod skyblock;
+mod users;
 
-pub fn build_routes() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    let health = warp::path("health")
-        .and(warp::get())
-        .map(|| {
-            warp::reply::json(&serde_json::json!({
-                "status": "healthy",
-                "timestamp": Utc::now().timestamp()
-            }))
-        });
-
-    let bazaar_routes = bazaar_routes();
-    let auction_routes = auctions_routes();
-    let calendar_routes = calendar_routes();
-
-    health
+pub use auctions::poll_auctions;
+pub use bazaar::poll_bazaar;
+
+/// Largest JSON request body accepted; bulk imports set their own limit.
+const MAX_JSON_BYTES: u64 = 64 * 1024;
+
+/// A JSON request body of at most [`MAX_JSON_BYTES`], rejected with `413` before it is read.
+pub(crate) fn json_body<T: DeserializeOwned + Send>() -> impl Filter<Extract = (T,), Error = Rejection> + Copy {
+    warp::body::content_length_limit(MAX_JSON_BYTES).and(warp::body::json())
+}
+
+pub fn build_routes() -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
+    let v1 = warp::path("v1").and(v1_routes());
+
+    let routes = health_routes()
+        .or(metrics_routes())
+        .or(docs_routes())
+        .or(admin_routes())
+        .or(v1)
+        .or(v1_routes())
+        .with(warp::cors()
+            .allow_any_origin()
+            .allow_methods(["GET", "POST", "PUT", "DELETE"])
+            .allow_headers(["authorization", "content-type"])
+            .expose_headers(["deprecation", "link"]))
+        .recover(handle_rejection);
+
+    compressed(warp::path::full().and(routes).map(mark_deprecated))
+        .with(warp::log("api"))
+}
+
+/// Every versioned route. A `/v2` gets its own function next to this one that reuses the
+/// route filters and handlers it keeps and swaps in new ones where it breaks compatibility.
+fn v1_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let auth_routes = limited(RouteGroup::Auth, auth_routes());
+    let bazaar_routes = limited(RouteGroup::Bazaar, bazaar_routes());
+    let auction_routes = limited(RouteGroup::Auction, auctions_routes());
+    let calendar_routes = limited(RouteGroup::Calendar, calendar_routes().or(users_routes()));
+    let skyblock_routes = limited(RouteGroup::Skyblock, skyblock_routes());
+
+    auth_routes
         .or(bazaar_routes)
         .or(auction_routes)
         .or(calendar_routes)
-        .with(warp::cors().allow_any_origin())
-        .with(warp::log("api"))
-}
\ No newline at end of file
+        .or(skyblock_routes)
+}
+
+/// The `/v1` routes are also served at the root for one release after `/v1` was introduced.
+/// Responses there, errors included, are marked deprecated and point at `/v1`.
+fn mark_deprecated(path: FullPath, reply: impl Reply) -> Response {
+    let mut response = reply.into_response();
+    let first = path.as_str().trim_start_matches('/').split('/').next().unwrap_or_default();
+    if RouteGroup::for_segment(first).is_some() {
+        let headers = response.headers_mut();
+        headers.insert("deprecation", HeaderValue::from_static("true"));
+        if let Ok(link) = HeaderValue::from_str(&format!("</v1{}>; rel=\"successor-version\"", path.as_str())) {
+            headers.insert(LINK, link);
+        }
+    }
+    response
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::http::header::RETRY_AFTER;
+use warp::http::HeaderValue;
+use warp::path::Peek;
+use warp::reject::Reject;
+use warp::{Filter, Rejection, Reply};
+use crate::api::error::ApiError;
+use crate::auth::{TokenStore, TOKENS};
+use crate::config::config;
+use crate::server::RemoteAddr;
+
+/// Buckets untouched for this long are full again and get dropped.
+const IDLE_BUCKET_TTL: Duration = Duration::from_secs(600);
+
+pub static RATE_LIMITER: LazyLock<RateLimiter> = LazyLock::new(|| RateLimiter::new(config().rate_limits.clone()));
+
+/// Routes sharing a budget, by their first path segment.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteGroup {
+    Calendar,
+    Auth,
+    Bazaar,
+    Auction,
+    Skyblock,
+}
+
+impl RouteGroup {
+    const ALL: [RouteGroup; 5] =
+        [RouteGroup::Calendar, RouteGroup::Auth, RouteGroup::Bazaar, RouteGroup::Auction, RouteGroup::Skyblock];
+
+    /// The group whose routes start with `segment`.
+    pub fn for_segment(segment: &str) -> Option<RouteGroup> {
+        RouteGroup::ALL.into_iter().find(|group| group.prefixes().contains(&segment))
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        match self {
+            RouteGroup::Calendar => &["calendar", "me", "users"],
+            RouteGroup::Auth => &["auth"],
+            RouteGroup::Bazaar => &["bazaar"],
+            RouteGroup::Auction => &["auction"],
+            RouteGroup::Skyblock => &["skyblock"],
+        }
+    }
+}
+
+/// A token bucket: up to `burst` requests at once, refilled at `per_minute`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub calendar: Limit,
+    pub auth: Limit,
+    pub bazaar: Limit,
+    /// Auction routes proxy Hypixel, so they get the smallest budget.
+    pub auction: Limit,
+    pub skyblock: Limit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            calendar: Limit { burst: 60, per_minute: 120 },
+            auth: Limit { burst: 10, per_minute: 10 },
+            bazaar: Limit { burst: 30, per_minute: 60 },
+            auction: Limit { burst: 10, per_minute: 20 },
+            skyblock: Limit { burst: 30, per_minute: 60 },
+        }
+    }
+}
+
+impl RateLimits {
+    pub fn get(&self, group: RouteGroup) -> Limit {
+        match group {
+            RouteGroup::Calendar => self.calendar,
+            RouteGroup::Auth => self.auth,
+            RouteGroup::Bazaar => self.bazaar,
+            RouteGroup::Auction => self.auction,
+            RouteGroup::Skyblock => self.skyblock,
+        }
+    }
+}
+
+/// Who a request is counted against. Users are counted by id, so every token of theirs
+/// shares one budget.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    User(Uuid),
+    Ip(IpAddr),
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Budget left after a request, sent back as `X-RateLimit-*` headers.
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+    limit: u32,
+    remaining: u32,
+    /// Seconds until the bucket is full again.
+    reset: u64,
+}
+
+impl Usage {
+    fn apply(&self, reply: impl Reply) -> warp::reply::Response {
+        let mut response = reply.into_response();
+        let headers = response.headers_mut();
+        headers.insert("x-ratelimit-limit", HeaderValue::from(self.limit));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from(self.remaining));
+        headers.insert("x-ratelimit-reset", HeaderValue::from(self.reset));
+        response
+    }
+}
+
+#[derive(Debug)]
+struct RateLimited {
+    usage: Usage,
+    retry_after: u64,
+}
+impl Reject for RateLimited {}
+
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Mutex<HashMap<(Client, RouteGroup), Bucket>>,
+    last_prune: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+            last_prune: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Takes one request from the client's bucket for `group`.
+    fn check(&self, client: Client, group: RouteGroup) -> Result<Usage, RateLimited> {
+        self.check_at(client, group, Instant::now())
+    }
+
+    fn check_at(&self, client: Client, group: RouteGroup, now: Instant) -> Result<Usage, RateLimited> {
+        let limit = self.limits.get(group);
+        let per_second = limit.per_minute.max(1) as f64 / 60.0;
+        self.prune(now);
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry((client, group))
+            .or_insert(Bucket { tokens: limit.burst as f64, updated: now });
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * per_second;
+        bucket.tokens = (bucket.tokens + refill).min(limit.burst as f64);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let usage = Usage {
+            limit: limit.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset: ((limit.burst as f64 - bucket.tokens) / per_second).ceil() as u64,
+        };
+        if allowed {
+            Ok(usage)
+        } else {
+            Err(RateLimited { usage, retry_after: ((1.0 - bucket.tokens) / per_second).ceil() as u64 })
+        }
+    }
+
+    fn prune(&self, now: Instant) {
+        let mut last_prune = self.last_prune.lock().unwrap();
+        if now.duration_since(*last_prune) < IDLE_BUCKET_TTL {
+            return;
+        }
+        *last_prune = now;
+        self.buckets.lock().unwrap().retain(|_, bucket| now.duration_since(bucket.updated) < IDLE_BUCKET_TTL);
+    }
+}
+
+/// The user of a valid API token, or else the client's IP.
+fn identify(authorization: Option<&str>, remote: Option<IpAddr>, tokens: &TokenStore) -> Client {
+    let user = authorization
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .and_then(|token| tokens.resolve(token.trim()))
+        .map(|token| token.user);
+    match (user, remote) {
+        (Some(user), _) => Client::User(user),
+        (None, Some(ip)) => Client::Ip(ip),
+        (None, None) => Client::Unknown,
+    }
+}
+
+fn client() -> impl Filter<Extract = (Client,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::ext::optional::<RemoteAddr>())
+        .map(|authorization: Option<String>, remote: Option<RemoteAddr>| {
+            identify(authorization.as_deref(), remote.map(|RemoteAddr(addr)| addr.ip()), &TOKENS.read().unwrap())
+        })
+}
+
+/// Applies the `group` budget to `routes`, adding usage headers to their replies.
+pub fn limited<F, R>(group: RouteGroup, routes: F) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    warp::path::peek()
+        .and_then(move |peek: Peek| async move {
+            match peek.segments().next() {
+                Some(segment) if group.prefixes().contains(&segment) => Ok(()),
+                _ => Err(warp::reject::not_found()),
+            }
+        })
+        .untuple_one()
+        .and(client())
+        .and_then(move |client: Client| async move {
+            RATE_LIMITER.check(client, group).map_err(warp::reject::custom)
+        })
+        .and(routes)
+        .map(|usage: Usage, reply: R| usage.apply(reply))
+}
+
+/// The 429 for a rate limited request, with `Retry-After` and the usage headers.
+pub(crate) fn rejection_response(rejection: &Rejection) -> Option<warp::reply::Response> {
+    let limited = rejection.find::<RateLimited>()?;
+    let mut response = limited.usage.apply(ApiError::RateLimited { retry_after: limited.retry_after });
+    response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(limited.retry_after));
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Scope;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimits { auction: Limit { burst: 2, per_minute: 6 }, ..RateLimits::default() })
+    }
+
+    #[test]
+    fn buckets_allow_a_burst_then_refill() {
+        let limiter = limiter();
+        let client = Client::Ip(IpAddr::from([127, 0, 0, 1]));
+        let start = Instant::now();
+
+        let usage = limiter.check_at(client.clone(), RouteGroup::Auction, start).unwrap();
+        assert_eq!((usage.limit, usage.remaining, usage.reset), (2, 1, 10));
+        assert!(limiter.check_at(client.clone(), RouteGroup::Auction, start).is_ok());
+        let limited = limiter.check_at(client.clone(), RouteGroup::Auction, start).unwrap_err();
+        assert_eq!((limited.retry_after, limited.usage.remaining), (10, 0));
+
+        // Six a minute is one every ten seconds.
+        assert!(limiter.check_at(client.clone(), RouteGroup::Auction, start + Duration::from_secs(9)).is_err());
+        assert!(limiter.check_at(client.clone(), RouteGroup::Auction, start + Duration::from_secs(10)).is_ok());
+        assert!(limiter.check_at(client.clone(), RouteGroup::Auction, start + Duration::from_secs(10)).is_err());
+        // Never more than the burst, however long the client was idle.
+        let later = start + Duration::from_secs(300);
+        assert!(limiter.check_at(client.clone(), RouteGroup::Auction, later).is_ok());
+        assert!(limiter.check_at(client.clone(), RouteGroup::Auction, later).is_ok());
+        assert!(limiter.check_at(client, RouteGroup::Auction, later).is_err());
+    }
+
+    #[test]
+    fn buckets_are_per_client_and_group() {
+        let limiter = limiter();
+        let now = Instant::now();
+        let (alice, bob) = (Client::User(Uuid::new_v4()), Client::User(Uuid::new_v4()));
+        for _ in 0..2 {
+            assert!(limiter.check_at(alice.clone(), RouteGroup::Auction, now).is_ok());
+        }
+        assert!(limiter.check_at(alice.clone(), RouteGroup::Auction, now).is_err());
+        assert!(limiter.check_at(bob, RouteGroup::Auction, now).is_ok());
+        assert!(limiter.check_at(alice, RouteGroup::Bazaar, now).is_ok());
+    }
+
+    #[test]
+    fn every_token_of_a_user_shares_a_budget() {
+        let user = Uuid::new_v4();
+        let mut tokens = TokenStore::default();
+        let first = tokens.issue(user, String::new(), Scope::DEFAULT.to_vec());
+        let second = tokens.issue(user, String::new(), vec![Scope::ReadCalendars]);
+        let ip = IpAddr::from([10, 0, 0, 7]);
+
+        let bearer = |token: &str| format!("Bearer {}", token);
+        assert_eq!(identify(Some(&bearer(&first)), Some(ip), &tokens), Client::User(user));
+        assert_eq!(identify(Some(&bearer(&second)), Some(ip), &tokens), Client::User(user));
+        assert_eq!(identify(Some(&bearer("sbc_made_up")), Some(ip), &tokens), Client::Ip(ip));
+        assert_eq!(identify(Some(&first), Some(ip), &tokens), Client::Ip(ip));
+        assert_eq!(identify(None, None, &tokens), Client::Unknown);
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::Utc;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+use warp::{Filter, Rejection, Reply};
+use crate::api::auth::authenticated;
+use crate::api::error::{ApiError, ErrorBody};
+use crate::api::stream::{self, list_format, ListFormat};
+use crate::auth::Scope;
+use crate::calendar::database::DATABASE;
+use crate::calendar::election::{Election, ElectionReport, ELECTION_HISTORY};
+use crate::calendar::jacob::{Contest, ContestSchedule, Crop, JACOB_CONTESTS};
+
+const DEFAULT_CONTEST_LIMIT: usize = 10;
+/// Imports carry whole years of contests, so they may be larger than other bodies.
+const MAX_IMPORT_BYTES: u64 = 4 * 1024 * 1024;
+
+pub fn skyblock_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let skyblock_path = warp::path("skyblock");
+
+    // GET /skyblock/jacob?crop=CROP_NAME&limit=10
+    let jacob = skyblock_path
+        .and(warp::path("jacob"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<JacobQuery>())
+        .then(jacob_contests_handler);
+
+    // POST /skyblock/jacob/import
+    let import_jacob = skyblock_path
+        .and(warp::path("jacob"))
+        .and(warp::path("import"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(authenticated(Scope::ManageTracking))
+        .and(warp::body::content_length_limit(MAX_IMPORT_BYTES))
+        .and(warp::body::json())
+        .then(import_jacob_contests_handler);
+
+    // GET /skyblock/election/current
+    let current_election = skyblock_path
+        .and(warp::path("election"))
+        .and(warp::path("current"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .then(current_election_handler);
+
+    // GET /skyblock/election/history
+    let election_history = skyblock_path
+        .and(warp::path("election"))
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(list_format())
+        .map(election_history_handler);
+
+    // GET /skyblock/election/{year}
+    let election_year = skyblock_path
+        .and(warp::path("election"))
+        .and(warp::path::param::<i16>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .then(election_year_handler);
+
+    jacob
+        .or(import_jacob)
+        .or(current_election)
+        .or(election_history)
+        .or(election_year)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct JacobQuery {
+    /// Only contests featuring this crop, e.g. `nether_wart`.
+    crop: Option<String>,
+    #[param(default = 10)]
+    limit: Option<usize>,
+}
+
+/// Upcoming Jacob's contests with known crops.
+#[utoipa::path(get, path = "/skyblock/jacob", tag = "skyblock", params(JacobQuery),
+    responses((status = 200, body = Vec<Contest>), (status = 400, body = ErrorBody)))]
+async fn jacob_contests_handler(query: JacobQuery) -> Result<impl Reply, ApiError> {
+    let crop = query.crop.as_deref()
+        .map(str::parse::<Crop>)
+        .transpose()
+        .map_err(ApiError::BadRequest)?;
+
+    let contests = JACOB_CONTESTS.read().unwrap()
+        .upcoming(Utc::now(), crop, query.limit.unwrap_or(DEFAULT_CONTEST_LIMIT));
+    Ok(warp::reply::json(&contests))
+}
+
+/// Merges contest crops in the community tracker format.
+#[utoipa::path(post, path = "/skyblock/jacob/import", tag = "skyblock", security(("bearer" = ["manage_tracking"])),
+    request_body = ContestSchedule,
+    responses((status = 200, body = Object), (status = 400, body = ErrorBody), (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody)))]
+async fn import_jacob_contests_handler(_user: Uuid, schedule: ContestSchedule) -> Result<impl Reply, ApiError> {
+    let (imported, total, saved) = {
+        let mut contests = JACOB_CONTESTS.write().unwrap();
+        let imported = contests.import(schedule).map_err(ApiError::BadRequest)?;
+        (imported, contests.contests.len(), contests.save())
+    };
+    // The imported crops are live either way, so the calendar reflects them before reporting a failed save.
+    DATABASE.write().unwrap().refresh_skyblock_calendar();
+    saved.map_err(ApiError::storage)?;
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "message": format!("Imported {} Jacob's contests", imported),
+        "imported": imported,
+        "total": total
+    })))
+}
+
+/// The mayor in office and the running election.
+#[utoipa::path(get, path = "/skyblock/election/current", tag = "skyblock",
+    responses((status = 200, body = Object), (status = 404, body = ErrorBody)))]
+async fn current_election_handler() -> Result<impl Reply, ApiError> {
+    let history = ELECTION_HISTORY.read().unwrap();
+    let Some(mayor) = history.mayor() else {
+        return Err(ApiError::NotFound("No election data collected yet".to_string()));
+    };
+    let election = history.current().and_then(|current| history.report(current.year));
+
+    Ok(warp::reply::json(&serde_json::json!({
+        "mayor": mayor,
+        "election": election
+    })))
+}
+
+/// Every recorded election, streamed.
+#[utoipa::path(get, path = "/skyblock/election/history", tag = "skyblock",
+    responses((status = 200, content((Vec<Election> = "application/json"), (Election = "application/x-ndjson")))))]
+fn election_history_handler(format: ListFormat) -> warp::reply::Response {
+    let history = ELECTION_HISTORY.read().unwrap().history();
+    stream::list_of(format, history)
+}
+
+#[utoipa::path(get, path = "/skyblock/election/{year}", tag = "skyblock",
+    params(("year" = i16, Path, description = "Skyblock year the election was held in")),
+    responses((status = 200, body = ElectionReport), (status = 404, body = ErrorBody)))]
+async fn election_year_handler(year: i16) -> Result<impl Reply, ApiError> {
+    let report = ELECTION_HISTORY.read().unwrap()
+        .report(year)
+        .ok_or_else(|| ApiError::NotFound(format!("No election data for year {}", year)))?;
+    Ok(warp::reply::json(&report))
+}
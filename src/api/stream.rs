@@ -0,0 +1,132 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use futures_util::{Stream, StreamExt};
+use hyper::body::Bytes;
+use serde::Serialize;
+use warp::http::header::CONTENT_TYPE;
+use warp::http::HeaderValue;
+use warp::reply::Response;
+use warp::Filter;
+
+/// Items sent per chunk of a streamed list.
+pub const BATCH_SIZE: usize = 500;
+
+pub type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// A body sent chunk by chunk. warp can only build bodies from bytes, so the stream travels in
+/// the response extensions and `server::serve` sends it in place of the (empty) warp body.
+#[derive(Clone)]
+pub struct Streamed(Arc<Mutex<Option<ByteStream>>>);
+
+impl Streamed {
+    pub fn attach(response: &mut Response, stream: ByteStream) {
+        response.extensions_mut().insert(Streamed(Arc::new(Mutex::new(Some(stream)))));
+    }
+
+    /// Removes the stream from `response`, if it has one.
+    pub fn take(response: &mut Response) -> Option<ByteStream> {
+        response.extensions_mut().remove::<Streamed>()?.0.lock().unwrap().take()
+    }
+
+    pub fn is_streamed(response: &Response) -> bool {
+        response.extensions().get::<Streamed>().is_some()
+    }
+}
+
+/// How a streamed list is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+    /// One JSON array, the default.
+    Array,
+    /// One JSON value per line, for `Accept: application/x-ndjson`.
+    Ndjson,
+}
+
+impl ListFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ListFormat::Array => "application/json",
+            ListFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+/// The list format asked for in the `Accept` header.
+pub fn list_format() -> impl Filter<Extract = (ListFormat,), Error = std::convert::Infallible> + Clone {
+    warp::header::headers_cloned().map(|headers: warp::http::HeaderMap| {
+        let ndjson = headers.get_all(warp::http::header::ACCEPT).iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|media| media.split(';').next().unwrap_or_default().trim() == "application/x-ndjson");
+        if ndjson { ListFormat::Ndjson } else { ListFormat::Array }
+    })
+}
+
+fn encode_batch<T: Serialize>(format: ListFormat, batch: &[T], first: bool) -> io::Result<Bytes> {
+    let mut out = Vec::new();
+    for (i, item) in batch.iter().enumerate() {
+        if format == ListFormat::Array && !(first && i == 0) {
+            out.push(b',');
+        }
+        serde_json::to_writer(&mut out, item)?;
+        if format == ListFormat::Ndjson {
+            out.push(b'\n');
+        }
+    }
+    Ok(Bytes::from(out))
+}
+
+/// Streams `batches` as one list without holding all of it in memory. An error ends the
+/// body early, so clients see a truncated transfer rather than a valid but partial list.
+pub fn list<T, S>(format: ListFormat, batches: S) -> Response
+where
+    T: Serialize + Send + 'static,
+    S: Stream<Item = io::Result<Vec<T>>> + Send + 'static,
+{
+    let mut first = true;
+    let items = batches
+        .filter(|batch| std::future::ready(!matches!(batch, Ok(batch) if batch.is_empty())))
+        .map(move |batch| {
+            let chunk = batch.and_then(|batch| encode_batch(format, &batch, first));
+            first = false;
+            chunk
+        });
+    let body: ByteStream = match format {
+        ListFormat::Array => Box::pin(
+            futures_util::stream::once(async { Ok(Bytes::from_static(b"[")) })
+                .chain(items)
+                .chain(futures_util::stream::once(async { Ok(Bytes::from_static(b"]")) })),
+        ),
+        ListFormat::Ndjson => Box::pin(items),
+    };
+
+    let mut response = Response::new(Bytes::new().into());
+    response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+    Streamed::attach(&mut response, body);
+    response
+}
+
+/// Streams an in-memory list in batches, serializing one batch at a time.
+pub fn list_of<T>(format: ListFormat, items: Vec<T>) -> Response
+where
+    T: Serialize + Send + 'static,
+{
+    let mut items = items.into_iter().peekable();
+    let batches = futures_util::stream::iter(std::iter::from_fn(move || {
+        items.peek()?;
+        Some(Ok(items.by_ref().take(BATCH_SIZE).collect::<Vec<T>>()))
+    }));
+    list(format, batches)
+}
+
+/// Streams a list shared with other responses, copying one batch at a time.
+pub fn list_shared<T>(format: ListFormat, items: Arc<Vec<T>>) -> Response
+where
+    T: Serialize + Clone + Send + Sync + 'static,
+{
+    let batches = futures_util::stream::iter((0..items.len()).step_by(BATCH_SIZE).map(move |start| {
+        Ok(items[start..items.len().min(start + BATCH_SIZE)].to_vec())
+    }));
+    list(format, batches)
+}
@@ -0,0 +1,600 @@
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::reply::Json;
+use warp::{Filter, Rejection, Reply};
+use crate::api::auth::authenticated;
+use crate::api::error::{ApiError, ErrorBody};
+use crate::api::calendar::{parse_time, parse_timezone, EventsQuery};
+use crate::api::json_body;
+use crate::auth::Scope;
+use crate::calendar::agenda::{Agenda, Period, Units, View};
+use crate::calendar::calendar::{Access, Calendar, Category, Event, EventPage, EventQuery, Visibility};
+use crate::calendar::database::{DataBase, DatabaseError, Preferences, Subscription, UsersSnapshot, DATABASE};
+use crate::calendar::freebusy::{self, FreeBusy, Interval};
+use crate::calendar::skyblock::SkyblockDateTime;
+use crate::notifications::check_remind;
+
+/// Shortest time between occurrences of a recurring event.
+const MIN_RECURRENCE_SECS: i64 = 600;
+/// Longest time between occurrences, enough for yearly events.
+const MAX_RECURRENCE_SECS: i64 = 366 * 24 * 3600;
+/// Longest window free/busy is computed or a free slot searched in.
+const MAX_FREE_BUSY_DAYS: i64 = 31;
+/// Most users free/busy is computed for at once.
+const MAX_FREE_BUSY_USERS: usize = 50;
+/// How far ahead a free slot is searched by default.
+const DEFAULT_FREE_SLOT_DAYS: i64 = 7;
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+pub fn users_routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let me_path = warp::path("me");
+    let users_path = warp::path("users");
+    let read = || authenticated(Scope::ReadCalendars);
+    let write = || authenticated(Scope::WriteCalendars);
+
+    // GET /me/calendars
+    let calendars = me_path
+        .and(warp::path("calendars"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(read())
+        .then(calendars_handler);
+
+    // POST /me/calendars
+    let create_calendar = me_path
+        .and(warp::path("calendars"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(write())
+        .and(json_body())
+        .then(create_calendar_handler);
+
+    // POST /me/calendars/{calendar}/events
+    let create_event = me_path
+        .and(warp::path("calendars"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(write())
+        .and(json_body())
+        .then(create_event_handler);
+
+    // PUT /me/calendars/{calendar}/shares/{other_user}
+    let share = me_path
+        .and(warp::path("calendars"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("shares"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(write())
+        .and(json_body())
+        .then(share_handler);
+
+    // DELETE /me/calendars/{calendar}/shares/{other_user}
+    let unshare = me_path
+        .and(warp::path("calendars"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("shares"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(write())
+        .then(unshare_handler);
+
+    // GET /me/subscriptions
+    let subscriptions = me_path
+        .and(warp::path("subscriptions"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(read())
+        .then(subscriptions_handler);
+
+    // PUT /me/subscriptions/{calendar}
+    let subscribe = me_path
+        .and(warp::path("subscriptions"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(write())
+        .and(json_body())
+        .then(subscribe_handler);
+
+    // DELETE /me/subscriptions/{calendar}
+    let unsubscribe = me_path
+        .and(warp::path("subscriptions"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(write())
+        .then(unsubscribe_handler);
+
+    // GET /me/preferences
+    let preferences = me_path
+        .and(warp::path("preferences"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(read())
+        .then(preferences_handler);
+
+    // PUT /me/preferences
+    let set_preferences = me_path
+        .and(warp::path("preferences"))
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(write())
+        .and(json_body())
+        .then(set_preferences_handler);
+
+    // GET /me/agenda?from=&to=&q=&tag=&category=&ongoing=&limit=&cursor=&order=&tz=
+    let agenda = me_path
+        .and(warp::path("agenda"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(read())
+        .and(warp::query::<EventsQuery>())
+        .then(agenda_handler);
+
+    // GET /users/{id}/agenda?view=day|week|month&date=&units=real|skyblock&tz=
+    let agenda_view = users_path
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("agenda"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(read())
+        .and(warp::query::<AgendaViewQuery>())
+        .then(agenda_view_handler);
+
+    // GET /users/freebusy?users=&from=&to=&min_free=
+    let free_busy = users_path
+        .and(warp::path("freebusy"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(read())
+        .and(warp::query::<FreeBusyQuery>())
+        .then(free_busy_handler);
+
+    // GET /users/free-slot?users=&minutes=&after=&within=&avoid=
+    let free_slot = users_path
+        .and(warp::path("free-slot"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(read())
+        .and(warp::query::<FreeSlotQuery>())
+        .then(free_slot_handler);
+
+    calendars
+        .or(create_calendar)
+        .or(create_event)
+        .or(share)
+        .or(unshare)
+        .or(subscriptions)
+        .or(subscribe)
+        .or(unsubscribe)
+        .or(preferences)
+        .or(set_preferences)
+        .or(agenda)
+        .or(agenda_view)
+        .or(free_busy)
+        .or(free_slot)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct NewCalendar {
+    title: String,
+    description: Option<String>,
+    #[serde(default)]
+    visibility: Visibility,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct NewEvent {
+    title: String,
+    #[serde(default)]
+    description: String,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    /// Seconds between occurrences, 0 for a one-off event and otherwise between 600 and a year.
+    #[serde(default)]
+    recurrence: i64,
+    /// Seconds before the start to be reminded, at most a day.
+    #[serde(default)]
+    remind: i64,
+    #[serde(default)]
+    category: Category,
+    #[serde(default)]
+    tags: Vec<String>,
+    color: Option<String>,
+    icon: Option<String>,
+    /// IANA timezone the event recurs in, keeping its local time across DST. Defaults to the
+    /// user's preference.
+    timezone: Option<String>,
+}
+
+impl NewEvent {
+    fn into_event(self, default_timezone: Option<Tz>) -> Result<Event, String> {
+        if self.end_time < self.start_time {
+            return Err("end_time must not be before start_time".to_string());
+        }
+        if self.recurrence < 0 {
+            return Err("recurrence must not be negative".to_string());
+        }
+        check_remind(self.remind)?;
+        if self.recurrence > 0 && !(MIN_RECURRENCE_SECS..=MAX_RECURRENCE_SECS).contains(&self.recurrence) {
+            return Err(format!("recurrence must be 0 or between {} and {} seconds", MIN_RECURRENCE_SECS, MAX_RECURRENCE_SECS));
+        }
+        let notify_at = Duration::try_seconds(self.remind)
+            .and_then(|remind| self.start_time.checked_sub_signed(remind))
+            .ok_or_else(|| "remind is too far before start_time".to_string())?;
+        let duration = (self.end_time - self.start_time).num_seconds();
+        let tags: Vec<&str> = self.tags.iter().map(String::as_str).collect();
+        let mut event = Event::new(
+            self.title,
+            self.description,
+            notify_at,
+            self.start_time,
+            self.end_time,
+            duration,
+            self.recurrence,
+            self.remind,
+        )
+        .with_category(self.category)
+        .with_tags(&tags);
+        if let Some(color) = self.color {
+            event = event.with_color(&color);
+        }
+        if let Some(icon) = self.icon {
+            event = event.with_icon(&icon);
+        }
+        let timezone = self.timezone.as_deref().map(parse_timezone).transpose()?.or(default_timezone);
+        if let Some(timezone) = timezone {
+            event = event.with_timezone(timezone);
+        }
+        Ok(event)
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct ShareRequest {
+    access: Access,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct SubscribeRequest {
+    /// Seconds before each event to be reminded, at most a day, overriding the calendar's events.
+    remind: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AgendaViewQuery {
+    #[serde(default)]
+    view: View,
+    /// A day of the period to show, `YYYY-MM-DD`, or a Skyblock `year-month-day` in Skyblock
+    /// units. Defaults to today.
+    date: Option<String>,
+    #[serde(default)]
+    units: Units,
+    /// IANA timezone days start in and local times are given in. Defaults to the user's
+    /// preference, then UTC.
+    tz: Option<String>,
+}
+
+impl AgendaViewQuery {
+    fn period(&self, timezone: Option<Tz>, now: DateTime<Utc>) -> Result<Period, String> {
+        match self.units {
+            Units::Real => {
+                let timezone = timezone.unwrap_or(Tz::UTC);
+                let date = match self.date.as_deref() {
+                    Some(date) => date.parse::<NaiveDate>()
+                        .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date))?,
+                    None => now.with_timezone(&timezone).date_naive(),
+                };
+                Ok(Period::real(self.view, date, timezone))
+            }
+            Units::Skyblock => {
+                let date = match self.date.as_deref() {
+                    Some(date) => date.parse::<SkyblockDateTime>()?,
+                    None => SkyblockDateTime::date_to_skyblock(now),
+                };
+                Period::skyblock(self.view, date)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct FreeBusyQuery {
+    /// Comma-separated user ids. Defaults to the caller.
+    users: Option<String>,
+    /// RFC 3339 or unix seconds. Defaults to now.
+    from: Option<String>,
+    /// RFC 3339 or unix seconds. Defaults to a day after `from`.
+    to: Option<String>,
+    /// Shortest free slot to list, in minutes.
+    #[serde(default)]
+    min_free: i64,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct FreeSlotQuery {
+    /// Comma-separated user ids. Defaults to the caller.
+    users: Option<String>,
+    /// Length of the slot.
+    minutes: i64,
+    /// Earliest start, RFC 3339 or unix seconds. Defaults to now.
+    after: Option<String>,
+    /// Days after `after` to search.
+    #[param(default = 7)]
+    within: Option<i64>,
+    /// Comma-separated Skyblock events the slot must not overlap, e.g. `dark_auction`, or `mayor`
+    /// for mayor perk events.
+    avoid: Option<String>,
+}
+
+/// The listed user ids, or just the caller.
+fn parse_users(users: Option<&str>, caller: Uuid) -> Result<Vec<Uuid>, ApiError> {
+    let Some(users) = users.filter(|users| !users.trim().is_empty()) else {
+        return Ok(vec![caller]);
+    };
+    let mut ids = users.split(',')
+        .map(|id| id.trim().parse::<Uuid>().map_err(|_| ApiError::BadRequest(format!("Invalid user id '{}'", id.trim()))))
+        .collect::<Result<Vec<Uuid>, ApiError>>()?;
+    ids.sort();
+    ids.dedup();
+    if ids.len() > MAX_FREE_BUSY_USERS {
+        return Err(ApiError::BadRequest(format!("At most {} users can be listed", MAX_FREE_BUSY_USERS)));
+    }
+    Ok(ids)
+}
+
+fn parse_avoid(avoid: Option<&str>) -> Result<Vec<String>, ApiError> {
+    let known = freebusy::avoidable();
+    avoid.unwrap_or_default().split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| match known.contains(&key) {
+            true => Ok(key.to_string()),
+            false => Err(ApiError::BadRequest(format!("Unknown Skyblock event '{}', expected one of {}", key, known.join(", ")))),
+        })
+        .collect()
+}
+
+/// `time` moved on by `by`, which may not fit in a date for times given in the far future.
+fn later(time: DateTime<Utc>, by: Duration) -> Result<DateTime<Utc>, ApiError> {
+    time.checked_add_signed(by).ok_or_else(|| ApiError::BadRequest("The window ends too far in the future".to_string()))
+}
+
+fn check_window(from: DateTime<Utc>, to: DateTime<Utc>) -> Result<(), ApiError> {
+    if from >= to {
+        return Err(ApiError::BadRequest("from must be before to".to_string()));
+    }
+    if to - from > Duration::days(MAX_FREE_BUSY_DAYS) {
+        return Err(ApiError::BadRequest(format!("The window must not be longer than {} days", MAX_FREE_BUSY_DAYS)));
+    }
+    Ok(())
+}
+
+/// When any of `users` is busy in `[from, to)`. A user whose busy times `viewer` cannot read
+/// in full is refused rather than reported as free.
+fn users_busy(users: &[Uuid], viewer: Uuid, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Interval>, ApiError> {
+    let query = EventQuery { from: Some(from), to: Some(to), ..EventQuery::default() };
+    let database = DATABASE.read().unwrap();
+    let mut occurrences = Vec::new();
+    for user_id in users {
+        if !database.sees_busy_times(*user_id, viewer)? {
+            return Err(ApiError::Forbidden(format!(
+                "User {} has calendars with their own events that are not shared with you", user_id
+            )));
+        }
+        occurrences.extend(database.agenda_seen_by(*user_id, viewer, &query)?);
+    }
+    Ok(freebusy::busy(&occurrences, from, to))
+}
+
+/// Persists the users after a change, with the lock released; the change stays in memory even
+/// when saving fails.
+pub(crate) async fn save(snapshot: Result<UsersSnapshot, serde_json::Error>) -> Result<(), ApiError> {
+    tokio::task::spawn_blocking(move || snapshot?.save())
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map_err(ApiError::storage)
+}
+
+/// Calendars the user owns or that are shared with them, with their access.
+#[utoipa::path(get, path = "/me/calendars", tag = "me", security(("bearer" = ["read_calendars"])),
+    responses((status = 200, body = Vec<Object>), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn calendars_handler(user_id: Uuid) -> Result<impl Reply, ApiError> {
+    let calendars: Vec<serde_json::Value> = DATABASE.read().unwrap()
+        .visible_calendars(user_id)?
+        .into_iter()
+        .map(|(summary, access)| serde_json::json!({ "calendar": summary, "access": access }))
+        .collect();
+    Ok(warp::reply::json(&calendars))
+}
+
+#[utoipa::path(post, path = "/me/calendars", tag = "me", security(("bearer" = ["write_calendars"])),
+    request_body = NewCalendar,
+    responses((status = 201, body = Object, example = json!({ "id": "9b2f2f8e-4c1e-4b55-9d6f-0d3c1c4c2a11" })), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn create_calendar_handler(user_id: Uuid, body: NewCalendar) -> Result<impl Reply, ApiError> {
+    let calendar = Calendar::new(body.title, body.description).with_visibility(body.visibility);
+    let (id, snapshot) = {
+        let mut database = DATABASE.write().unwrap();
+        let id = database.create_calendar(user_id, calendar)?;
+        (id, database.snapshot())
+    };
+    save(snapshot).await?;
+    Ok(warp::reply::with_status(warp::reply::json(&serde_json::json!({ "id": id })), StatusCode::CREATED))
+}
+
+#[utoipa::path(post, path = "/me/calendars/{calendar}/events", tag = "me", security(("bearer" = ["write_calendars"])),
+    params(("calendar" = Uuid, Path)),
+    request_body = NewEvent,
+    responses((status = 201, body = Object), (status = 400, body = ErrorBody), (status = 404, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn create_event_handler(calendar_id: Uuid, user_id: Uuid, body: NewEvent) -> Result<impl Reply, ApiError> {
+    let (id, snapshot) = {
+        let mut database = DATABASE.write().unwrap();
+        let event = body.into_event(database.preferences(user_id)?.timezone).map_err(ApiError::BadRequest)?;
+        let id = database.add_event(user_id, calendar_id, event)?;
+        (id, database.snapshot())
+    };
+    save(snapshot).await?;
+    Ok(warp::reply::with_status(warp::reply::json(&serde_json::json!({ "id": id })), StatusCode::CREATED))
+}
+
+/// Gives another user access to a calendar the caller owns.
+#[utoipa::path(put, path = "/me/calendars/{calendar}/shares/{other_user}", tag = "me", security(("bearer" = ["write_calendars"])),
+    params(("calendar" = Uuid, Path), ("other_user" = Uuid, Path)),
+    request_body = ShareRequest,
+    responses((status = 200, body = Object), (status = 404, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn share_handler(calendar_id: Uuid, with: Uuid, user_id: Uuid, body: ShareRequest) -> Result<impl Reply, ApiError> {
+    update(|database| database.share(user_id, calendar_id, with, Some(body.access))).await
+}
+
+#[utoipa::path(delete, path = "/me/calendars/{calendar}/shares/{other_user}", tag = "me", security(("bearer" = ["write_calendars"])),
+    params(("calendar" = Uuid, Path), ("other_user" = Uuid, Path)),
+    responses((status = 200, body = Object), (status = 404, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn unshare_handler(calendar_id: Uuid, with: Uuid, user_id: Uuid) -> Result<impl Reply, ApiError> {
+    update(|database| database.share(user_id, calendar_id, with, None)).await
+}
+
+#[utoipa::path(get, path = "/me/subscriptions", tag = "me", security(("bearer" = ["read_calendars"])),
+    responses((status = 200, body = Vec<Subscription>), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn subscriptions_handler(user_id: Uuid) -> Result<impl Reply, ApiError> {
+    let database = DATABASE.read().unwrap();
+    let user = database.get_user(user_id).ok_or(DatabaseError::UserNotFound(user_id))?;
+    Ok(warp::reply::json(&user.list_subscriptions()))
+}
+
+#[utoipa::path(put, path = "/me/subscriptions/{calendar}", tag = "me", security(("bearer" = ["write_calendars"])),
+    params(("calendar" = Uuid, Path)),
+    request_body = SubscribeRequest,
+    responses((status = 200, body = Object), (status = 404, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn subscribe_handler(calendar_id: Uuid, user_id: Uuid, body: SubscribeRequest) -> Result<impl Reply, ApiError> {
+    update(|database| database.subscribe(user_id, calendar_id, body.remind)).await
+}
+
+#[utoipa::path(delete, path = "/me/subscriptions/{calendar}", tag = "me", security(("bearer" = ["write_calendars"])),
+    params(("calendar" = Uuid, Path)),
+    responses((status = 200, body = Object), (status = 404, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn unsubscribe_handler(calendar_id: Uuid, user_id: Uuid) -> Result<impl Reply, ApiError> {
+    update(|database| database.unsubscribe(user_id, calendar_id).map(|_| ())).await
+}
+
+/// Applies a change without a result of its own and saves the users.
+async fn update(change: impl FnOnce(&mut DataBase) -> Result<(), DatabaseError>) -> Result<Json, ApiError> {
+    let snapshot = {
+        let mut database = DATABASE.write().unwrap();
+        change(&mut database)?;
+        database.snapshot()
+    };
+    save(snapshot).await?;
+    Ok(warp::reply::json(&serde_json::json!({ "status": "ok" })))
+}
+
+#[utoipa::path(get, path = "/me/preferences", tag = "me", security(("bearer" = ["read_calendars"])),
+    responses((status = 200, body = Preferences), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn preferences_handler(user_id: Uuid) -> Result<impl Reply, ApiError> {
+    Ok(warp::reply::json(&DATABASE.read().unwrap().preferences(user_id)?))
+}
+
+/// Replaces the user's preferences; a `null` timezone clears it.
+#[utoipa::path(put, path = "/me/preferences", tag = "me", security(("bearer" = ["write_calendars"])),
+    request_body = Preferences,
+    responses((status = 200, body = Preferences), (status = 400, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn set_preferences_handler(user_id: Uuid, preferences: Preferences) -> Result<impl Reply, ApiError> {
+    let snapshot = {
+        let mut database = DATABASE.write().unwrap();
+        database.set_preferences(user_id, preferences.clone())?;
+        database.snapshot()
+    };
+    save(snapshot).await?;
+    Ok(warp::reply::json(&preferences))
+}
+
+/// Occurrences from the user's own, shared and subscribed calendars, paged. Times are also
+/// given in `tz`, or else the user's preferred timezone.
+#[utoipa::path(get, path = "/me/agenda", tag = "me", security(("bearer" = ["read_calendars"])),
+    params(EventsQuery),
+    responses((status = 200, body = EventPage), (status = 400, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody)))]
+async fn agenda_handler(user_id: Uuid, params: EventsQuery) -> Result<impl Reply, ApiError> {
+    let mut query = params.into_query().map_err(ApiError::BadRequest)?;
+    let database = DATABASE.read().unwrap();
+    if query.timezone.is_none() {
+        query.timezone = database.preferences(user_id)?.timezone;
+    }
+    let occurrences = database.agenda(user_id, &query)?;
+    Ok(warp::reply::json(&query.page(occurrences)))
+}
+
+/// A user's occurrences bucketed into the days of a day, week or month, with multi-day events on
+/// every day they run and overlapping personal or guild events marked. Other users only see the
+/// calendars they can read themselves.
+#[utoipa::path(get, path = "/users/{id}/agenda", tag = "me", security(("bearer" = ["read_calendars"])),
+    params(("id" = Uuid, Path), AgendaViewQuery),
+    responses((status = 200, body = Agenda), (status = 400, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody), (status = 404, body = ErrorBody)))]
+async fn agenda_view_handler(user_id: Uuid, viewer: Uuid, params: AgendaViewQuery) -> Result<impl Reply, ApiError> {
+    let requested = params.tz.as_deref().map(parse_timezone).transpose().map_err(ApiError::BadRequest)?;
+    let database = DATABASE.read().unwrap();
+    let timezone = requested.or(database.preferences(user_id)?.timezone);
+    let now = Utc::now();
+    let period = params.period(timezone, now).map_err(ApiError::BadRequest)?;
+    let query = EventQuery { from: Some(period.from()), to: Some(period.to()), now, ..EventQuery::default() };
+    let occurrences = database.agenda_seen_by(user_id, viewer, &query)?;
+    Ok(warp::reply::json(&Agenda::build(period, occurrences, timezone)))
+}
+
+/// Merged busy times of the users and the free slots between them. Only personal and guild events
+/// make someone busy; every calendar holding them must be readable by the caller.
+#[utoipa::path(get, path = "/users/freebusy", tag = "me", security(("bearer" = ["read_calendars"])),
+    params(FreeBusyQuery),
+    responses((status = 200, body = FreeBusy), (status = 400, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody), (status = 404, body = ErrorBody)))]
+async fn free_busy_handler(viewer: Uuid, params: FreeBusyQuery) -> Result<impl Reply, ApiError> {
+    let users = parse_users(params.users.as_deref(), viewer)?;
+    if !(0..=MAX_FREE_BUSY_DAYS * MINUTES_PER_DAY).contains(&params.min_free) {
+        return Err(ApiError::BadRequest(format!("min_free must be between 0 and {} minutes", MAX_FREE_BUSY_DAYS * MINUTES_PER_DAY)));
+    }
+    let from = params.from.as_deref().map(parse_time).transpose().map_err(ApiError::BadRequest)?.unwrap_or_else(Utc::now);
+    let to = match params.to.as_deref() {
+        Some(to) => parse_time(to).map_err(ApiError::BadRequest)?,
+        None => later(from, Duration::days(1))?,
+    };
+    check_window(from, to)?;
+
+    let busy = users_busy(&users, viewer, from, to)?;
+    Ok(warp::reply::json(&FreeBusy::new(busy, from, to, Duration::minutes(params.min_free))))
+}
+
+/// The first slot of the given length where all users are free and none of the avoided Skyblock
+/// events runs.
+#[utoipa::path(get, path = "/users/free-slot", tag = "me", security(("bearer" = ["read_calendars"])),
+    params(FreeSlotQuery),
+    responses((status = 200, body = Interval), (status = 400, body = ErrorBody), (status = 401, body = ErrorBody), (status = 403, body = ErrorBody), (status = 404, body = ErrorBody)))]
+async fn free_slot_handler(viewer: Uuid, params: FreeSlotQuery) -> Result<impl Reply, ApiError> {
+    let users = parse_users(params.users.as_deref(), viewer)?;
+    let avoid = parse_avoid(params.avoid.as_deref())?;
+    let within = params.within.unwrap_or(DEFAULT_FREE_SLOT_DAYS);
+    if !(1..=MAX_FREE_BUSY_DAYS).contains(&within) {
+        return Err(ApiError::BadRequest(format!("within must be between 1 and {} days", MAX_FREE_BUSY_DAYS)));
+    }
+    if !(1..=within * MINUTES_PER_DAY).contains(&params.minutes) {
+        return Err(ApiError::BadRequest(format!("minutes must be between 1 and {}, the length of the search", within * MINUTES_PER_DAY)));
+    }
+    let from = params.after.as_deref().map(parse_time).transpose().map_err(ApiError::BadRequest)?.unwrap_or_else(Utc::now);
+    let to = later(from, Duration::days(within))?;
+    check_window(from, to)?;
+
+    let mut busy = users_busy(&users, viewer, from, to)?;
+    busy.extend(freebusy::skyblock_busy(&avoid, from, to));
+    let length = Duration::minutes(params.minutes);
+    let slot = freebusy::first_free(&freebusy::merge(busy), from, to, length)
+        .ok_or_else(|| ApiError::NotFound(format!("No free slot of {} minutes before {}", params.minutes, to.to_rfc3339_opts(SecondsFormat::Secs, true))))?;
+    Ok(warp::reply::json(&slot))
+}
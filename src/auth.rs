@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex, RwLock};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::de::Error;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use crate::config::data_file;
+use crate::helpers::{read_json_from_file, write_json_to_file};
+
+const TOKENS_FILE: &str = "tokens.json";
+const TOKEN_PREFIX: &str = "sbc_";
+
+pub static TOKENS: LazyLock<RwLock<TokenStore>> = LazyLock::new(|| RwLock::new(TokenStore::load()));
+
+/// What an API token may be used for.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    ReadCalendars,
+    WriteCalendars,
+    /// Auction and bazaar tracking and Jacob's contest imports.
+    ManageTracking,
+    /// The `/admin` routes.
+    Admin,
+}
+
+impl Scope {
+    pub const DEFAULT: [Scope; 2] = [Scope::ReadCalendars, Scope::WriteCalendars];
+
+    /// Scopes that change what every user sees; only users listed in `admin.users` get them.
+    pub fn is_privileged(self) -> bool {
+        matches!(self, Scope::ManageTracking | Scope::Admin)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scope::ReadCalendars => "read_calendars",
+            Scope::WriteCalendars => "write_calendars",
+            Scope::ManageTracking => "manage_tracking",
+            Scope::Admin => "admin",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Passwords are hashed as they are, so their length is bounded to bound the work.
+pub const MAX_PASSWORD_LENGTH: usize = 256;
+
+/// Checked when a login names no account, so unknown names take as long as wrong passwords.
+pub static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| hash_password("not the password of any account"));
+
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::encode_b64(Uuid::new_v4().as_bytes()).expect("a UUID is a valid salt");
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("argon2 accepts any password with the default parameters")
+        .to_string()
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+/// The user of `account` if `password` is theirs. Slow on purpose, so call it off the async runtime.
+pub fn verify_login(account: Option<(Uuid, Option<String>)>, password: &str) -> Option<Uuid> {
+    if password.chars().count() > MAX_PASSWORD_LENGTH {
+        return None;
+    }
+    match account {
+        Some((user, Some(hash))) => verify_password(password, &hash).then_some(user),
+        _ => {
+            verify_password(password, &DUMMY_HASH);
+            None
+        }
+    }
+}
+
+/// An issued bearer token. Only a hash of the token itself is kept.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiToken {
+    pub user: Uuid,
+    pub label: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ApiToken {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// Issued tokens keyed by the SHA-256 of the token.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TokenStore {
+    tokens: HashMap<String, ApiToken>,
+    /// Why an existing tokens file could not be loaded. Saving is refused then, so the
+    /// tokens in it are not overwritten with the empty set that was loaded instead.
+    #[serde(skip)]
+    load_error: Option<String>,
+}
+
+/// Numbers snapshots in the order they were taken, so an older one never overwrites a newer one.
+static SNAPSHOT_GENERATION: AtomicU64 = AtomicU64::new(0);
+/// Generation of the last snapshot written; also serialises the writes.
+static SAVED_GENERATION: Mutex<u64> = Mutex::new(0);
+
+/// The tokens to persist, taken while the lock is held and written after it is released.
+pub struct TokensSnapshot {
+    generation: u64,
+    tokens: HashMap<String, ApiToken>,
+}
+
+impl TokensSnapshot {
+    /// Writes the tokens unless a later snapshot was written already. Blocks on the file system,
+    /// so call it off the async runtime.
+    pub fn save(self) -> Result<(), serde_json::Error> {
+        let mut saved = SAVED_GENERATION.lock().unwrap();
+        if *saved > self.generation {
+            return Ok(());
+        }
+        let count = self.tokens.len();
+        write_json_to_file(data_file(TOKENS_FILE), &TokenStore { tokens: self.tokens, load_error: None })?;
+        *saved = self.generation;
+        info!("Saved {} API tokens to {}", count, TOKENS_FILE);
+        Ok(())
+    }
+}
+
+fn digest(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl TokenStore {
+    fn load() -> Self {
+        let path = data_file(TOKENS_FILE);
+        match read_json_from_file(&path) {
+            Ok(store) => store,
+            Err(e) if !path.exists() => {
+                warn!("No API tokens loaded, could not read {}: {}", TOKENS_FILE, e);
+                TokenStore::default()
+            }
+            Err(e) => {
+                error!("Could not load the API tokens from {}: {}", TOKENS_FILE, e);
+                TokenStore { load_error: Some(e.to_string()), ..TokenStore::default() }
+            }
+        }
+    }
+
+    /// Why the existing tokens file could not be loaded, if it couldn't.
+    pub fn load_error(&self) -> Option<&str> {
+        self.load_error.as_deref()
+    }
+
+    /// Issues a new token and returns it; it cannot be recovered afterwards.
+    pub fn issue(&mut self, user: Uuid, label: String, scopes: Vec<Scope>) -> String {
+        let token = format!("{}{}{}", TOKEN_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        self.tokens.insert(digest(&token), ApiToken { user, label, scopes, created_at: Utc::now() });
+        token
+    }
+
+    pub fn resolve(&self, token: &str) -> Option<&ApiToken> {
+        self.tokens.get(&digest(token))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn revoke(&mut self, token: &str) -> bool {
+        self.tokens.remove(&digest(token)).is_some()
+    }
+
+    /// Revokes every token of `user`; returns how many there were.
+    pub fn revoke_user(&mut self, user: Uuid) -> usize {
+        let before = self.tokens.len();
+        self.tokens.retain(|_, token| token.user != user);
+        before - self.tokens.len()
+    }
+
+    /// The tokens, to be saved once the lock is released.
+    pub fn snapshot(&self) -> Result<TokensSnapshot, serde_json::Error> {
+        if let Some(e) = &self.load_error {
+            return Err(serde_json::Error::custom(format!("{} could not be loaded, so it is not overwritten: {}", TOKENS_FILE, e)));
+        }
+        Ok(TokensSnapshot { generation: SNAPSHOT_GENERATION.fetch_add(1, Ordering::Relaxed) + 1, tokens: self.tokens.clone() })
+    }
+
+    pub fn save(&self) -> Result<(), serde_json::Error> {
+        self.snapshot()?.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_resolve_until_revoked() {
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut tokens = TokenStore::default();
+        let token = tokens.issue(alice, "bot".to_string(), Scope::DEFAULT.to_vec());
+        let other = tokens.issue(bob, String::new(), vec![Scope::ReadCalendars]);
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert!(!tokens.tokens.contains_key(&token), "only the digest is kept");
+
+        let resolved = tokens.resolve(&token).unwrap();
+        assert_eq!(resolved.user, alice);
+        assert!(resolved.allows(Scope::WriteCalendars));
+        assert!(!resolved.allows(Scope::ManageTracking));
+        assert!(tokens.resolve(&format!("{}x", token)).is_none());
+
+        assert!(tokens.revoke(&token));
+        assert!(!tokens.revoke(&token));
+        assert!(tokens.resolve(&token).is_none());
+        assert_eq!(tokens.revoke_user(bob), 1);
+        assert!(tokens.resolve(&other).is_none());
+    }
+
+    #[test]
+    fn tokens_that_failed_to_load_are_not_overwritten() {
+        let mut tokens = TokenStore { load_error: Some("EOF while parsing".to_string()), ..TokenStore::default() };
+        tokens.issue(Uuid::new_v4(), String::new(), Scope::DEFAULT.to_vec());
+        assert!(tokens.snapshot().is_err());
+        assert!(TokenStore::default().snapshot().is_ok());
+    }
+
+    #[test]
+    fn only_global_scopes_are_privileged() {
+        assert!(Scope::DEFAULT.iter().all(|scope| !scope.is_privileged()));
+        assert!(Scope::ManageTracking.is_privileged());
+        assert!(Scope::Admin.is_privileged());
+        assert_eq!(serde_json::to_string(&Scope::ManageTracking).unwrap(), format!("\"{}\"", Scope::ManageTracking));
+    }
+
+    #[test]
+    fn logins_need_the_right_password() {
+        let user = Uuid::new_v4();
+        let hash = hash_password("hunter22");
+        assert_eq!(verify_login(Some((user, Some(hash.clone()))), "hunter22"), Some(user));
+        assert_eq!(verify_login(Some((user, Some(hash.clone()))), "hunter23"), None);
+        assert_eq!(verify_login(Some((user, None)), "hunter22"), None);
+        assert_eq!(verify_login(None, "hunter22"), None);
+        assert_eq!(verify_login(Some((user, Some(hash))), &"a".repeat(MAX_PASSWORD_LENGTH + 1)), None);
+    }
+}
@@ -0,0 +1,203 @@
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::calendar::event::Event;
+use crate::calendar::skyblock::{SkyblockDateTime, DAYS_PER_MONTH};
+
+/// How much of the calendar an agenda covers.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum View {
+    #[default]
+    Day,
+    /// Monday to Sunday.
+    Week,
+    Month,
+}
+
+/// What a day of an agenda is.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    /// Days from midnight to midnight in a timezone.
+    #[default]
+    Real,
+    /// Skyblock days of 20 minutes, in Skyblock months.
+    Skyblock,
+}
+
+/// The days a view covers, each running from its `start` until the next one's.
+#[derive(Debug, Clone)]
+pub struct Period {
+    view: View,
+    units: Units,
+    days: Vec<(String, DateTime<Utc>)>,
+    end: DateTime<Utc>,
+}
+
+impl Period {
+    /// The day, week or month containing `date`, in days of `timezone`.
+    pub fn real(view: View, date: NaiveDate, timezone: Tz) -> Period {
+        let (first, count) = match view {
+            View::Day => (date, 1),
+            View::Week => (date - Duration::days(date.weekday().num_days_from_monday() as i64), 7),
+            View::Month => {
+                let first = date.with_day(1).unwrap_or(date);
+                let next = first.checked_add_months(Months::new(1)).unwrap_or(first);
+                (first, (next - first).num_days().max(1))
+            }
+        };
+        let days = (0..count)
+            .map(|offset| first + Duration::days(offset))
+            .map(|day| (day.to_string(), midnight(day, timezone)))
+            .collect();
+        Period { view, units: Units::Real, days, end: midnight(first + Duration::days(count), timezone) }
+    }
+
+    /// The Skyblock day or month containing `date`. The Skyblock calendar has no weeks.
+    pub fn skyblock(view: View, date: SkyblockDateTime) -> Result<Period, String> {
+        let (first, count) = match view {
+            View::Day => (date.start_of_day(), 1),
+            View::Week => return Err("The Skyblock calendar has no weeks, use view=day or view=month".to_string()),
+            View::Month => (date.start_of_day().add_days(1 - date.day() as i64), DAYS_PER_MONTH),
+        };
+        let days = (0..count)
+            .map(|offset| first.add_days(offset))
+            .map(|day| (format!("{}-{:02}-{:02}", day.year(), day.month(), day.day()), day.as_datetime()))
+            .collect();
+        Ok(Period { view, units: Units::Skyblock, days, end: first.add_days(count).as_datetime() })
+    }
+
+    pub fn from(&self) -> DateTime<Utc> {
+        self.days.first().map_or(self.end, |(_, start)| *start)
+    }
+
+    pub fn to(&self) -> DateTime<Utc> {
+        self.end
+    }
+}
+
+/// The start of `date` in `timezone`. Where clocks go forward at midnight the day starts an hour later.
+fn midnight(date: NaiveDate, timezone: Tz) -> DateTime<Utc> {
+    let local = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    timezone.from_local_datetime(&local).earliest()
+        .or_else(|| timezone.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .map_or_else(|| local.and_utc(), |start| start.with_timezone(&Utc))
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct AgendaEntry {
+    pub event: Event,
+    /// Started before this day.
+    pub continued: bool,
+    /// Still running when this day ends.
+    pub continues: bool,
+    /// Ids of the personal or guild events this one overlaps, if it is one itself.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct AgendaDay {
+    /// `year-month-day`, in Skyblock units a Skyblock date.
+    pub date: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Occurrences running at any time of the day, so multi-day events show up on every day.
+    pub events: Vec<AgendaEntry>,
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct Agenda {
+    pub view: View,
+    pub units: Units,
+    /// The timezone local times are given in.
+    #[schema(value_type = Option<String>)]
+    pub timezone: Option<Tz>,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Pairs of overlapping personal or guild occurrences.
+    pub conflicts: usize,
+    pub days: Vec<AgendaDay>,
+}
+
+/// Ids of the occurrences each of `occurrences` overlaps, and the number of overlapping pairs.
+fn find_conflicts(occurrences: &[Event]) -> (Vec<Vec<Uuid>>, usize) {
+    let mut conflicts = vec![Vec::new(); occurrences.len()];
+    let mut pairs = 0;
+    for (i, first) in occurrences.iter().enumerate().filter(|(_, event)| event.takes_time()) {
+        // Sorted by start, so nothing after an occurrence starting at the end of `first` overlaps it.
+        for (j, second) in occurrences.iter().enumerate().skip(i + 1) {
+            if second.start_time >= first.get_end_time() {
+                break;
+            }
+            if second.takes_time() && first.start_time < second.get_end_time() {
+                conflicts[i].push(second.get_id());
+                conflicts[j].push(first.get_id());
+                pairs += 1;
+            }
+        }
+    }
+    (conflicts, pairs)
+}
+
+impl Agenda {
+    /// Buckets `occurrences` into the days of `period`, with local times in `timezone`.
+    pub fn build(period: Period, mut occurrences: Vec<Event>, timezone: Option<Tz>) -> Agenda {
+        occurrences.sort_by(|a, b| (a.start_time, a.title()).cmp(&(b.start_time, b.title())));
+        let (conflicts, pairs) = find_conflicts(&occurrences);
+        let from = period.from();
+
+        let ends = period.days.iter().skip(1).map(|(_, start)| *start).chain([period.end]);
+        let days = period.days.iter().zip(ends)
+            .map(|((date, start), end)| AgendaDay {
+                date: date.clone(),
+                start: *start,
+                end,
+                events: occurrences.iter().zip(&conflicts)
+                    .filter(|(event, _)| event.overlaps(Some(*start), Some(end)))
+                    .map(|(event, conflicts)| AgendaEntry {
+                        event: match timezone {
+                            Some(timezone) => event.clone().localized(timezone),
+                            None => event.clone(),
+                        },
+                        continued: event.start_time < *start,
+                        continues: event.get_end_time() > end,
+                        conflicts: conflicts.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Agenda { view: period.view, units: period.units, timezone, from, to: period.end, conflicts: pairs, days }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::calendar::EventQuery;
+    use crate::calendar::skyblock::generate_calendar;
+
+    #[test]
+    fn skyblock_events_fill_a_past_month() {
+        let period = Period::real(View::Month, NaiveDate::from_ymd_opt(2026, 3, 10).unwrap(), Tz::Europe__Berlin);
+        let query = EventQuery { from: Some(period.from()), to: Some(period.to()), ..EventQuery::default() };
+        let occurrences = generate_calendar(period.from(), period.to()).occurrences(&query);
+        let agenda = Agenda::build(period, occurrences, None);
+
+        assert_eq!(agenda.days.len(), 31);
+        assert!(agenda.days.iter().all(|day| !day.events.is_empty()));
+    }
+
+    #[test]
+    fn a_skyblock_month_has_its_days() {
+        let period = Period::skyblock(View::Month, "402-3-17".parse().unwrap()).unwrap();
+        assert_eq!(period.days.len() as i64, DAYS_PER_MONTH);
+        assert_eq!(period.days[0].0, "402-03-01");
+        assert_eq!(period.to() - period.from(), Duration::minutes(20 * DAYS_PER_MONTH));
+        assert!(Period::skyblock(View::Week, "402-3-17".parse().unwrap()).is_err());
+    }
+}
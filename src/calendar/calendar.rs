@@ -1,16 +1,52 @@
 use std::collections::HashMap;
 use std::fmt;
 use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
-pub(crate) use crate::calendar::event::Event;
+pub(crate) use crate::calendar::event::{Category, Event};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// Only the owner and the users it is shared with.
+    #[default]
+    Private,
+    /// Anyone can read and subscribe to it.
+    Public,
+}
+
+/// What a user may do with a calendar, from least to most.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    Read,
+    Write,
+    Owner,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Calendar {
     id: Uuid,
     title: String,
     description: String,
     events: HashMap<Uuid, Event>,
+    #[serde(default)]
+    visibility: Visibility,
+    /// Users the calendar is shared with, besides its owner.
+    #[serde(default)]
+    shares: HashMap<Uuid, Access>,
+}
+
+/// A calendar without its events, as listed to users.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct CalendarSummary {
+    pub id: Uuid,
+    pub title: String,
+    pub description: String,
+    pub visibility: Visibility,
+    pub events: usize,
 }
 impl fmt::Display for Calendar {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -26,23 +62,75 @@ impl fmt::Display for Calendar {
 
 impl Calendar {
     pub fn new(title: String, description: Option<String>) -> Self {
-        if description.is_some() {
-            return Calendar { id: Uuid::new_v4(), title, description:description.unwrap(), events: HashMap::new() }
+        Calendar {
+            id: Uuid::new_v4(),
+            title,
+            description: description.unwrap_or_default(),
+            events: HashMap::new(),
+            visibility: Visibility::default(),
+            shares: HashMap::new(),
         }
-        Calendar {id: Uuid::new_v4(), title, description:"".to_string(), events: HashMap::new() }
-
-
+    }
+    pub fn with_visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+    /// Replaces the random id, for calendars that are regenerated and must keep their identity.
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
     }
     pub fn get_id(&self) -> &Uuid { &self.id }
-    pub fn add_event(&mut self, event: Event) {
-        self.events.insert(Uuid::new_v4(), event);
+    /// Replaces whichever of the title, description and visibility are given.
+    pub fn edit(&mut self, title: Option<String>, description: Option<String>, visibility: Option<Visibility>) {
+        if let Some(title) = title {
+            self.title = title;
+        }
+        if let Some(description) = description {
+            self.description = description;
+        }
+        if let Some(visibility) = visibility {
+            self.visibility = visibility;
+        }
+    }
+    pub fn add_event(&mut self, event: Event) -> Uuid {
+        let id = event.get_id();
+        self.events.insert(id, event);
+        id
     }
     pub fn get_event(&self, event_id: Uuid) -> Option<&Event> {
         self.events.get(&event_id)
     }
+    #[allow(dead_code)]
     pub fn get_description(&self) -> &str {
         &self.description
     }
+    pub fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+    pub fn summary(&self) -> CalendarSummary {
+        CalendarSummary {
+            id: self.id,
+            title: self.title.clone(),
+            description: self.description.clone(),
+            visibility: self.visibility,
+            events: self.events.len(),
+        }
+    }
+    /// Access `user_id` has through sharing or visibility; ownership is tracked by the [`User`](crate::calendar::database::User).
+    pub fn shared_access(&self, user_id: &Uuid) -> Option<Access> {
+        let public = (self.visibility == Visibility::Public).then_some(Access::Read);
+        self.shares.get(user_id).copied().max(public)
+    }
+    pub fn is_shared_with(&self, user_id: &Uuid) -> bool {
+        self.shares.contains_key(user_id)
+    }
+    pub fn share(&mut self, user_id: Uuid, access: Access) {
+        self.shares.insert(user_id, access);
+    }
+    pub fn unshare(&mut self, user_id: &Uuid) -> bool {
+        self.shares.remove(user_id).is_some()
+    }
     pub fn list_events(&self) -> Vec<&Event> {
         self.events.values().collect()
     }
@@ -54,4 +142,147 @@ impl Calendar {
         upcoming_events.sort_by_key(|event| event.start_time);
         upcoming_events
     }
+
+    /// Every occurrence matching `query`, unsorted and unpaged; see [`EventQuery::page`].
+    pub fn occurrences(&self, query: &EventQuery) -> Vec<Event> {
+        let window_end = query.window_end();
+        self.events.values()
+            .filter(|event| query.matches(event))
+            .flat_map(|event| match query.ongoing {
+                // Expanded around `now`, as without a window only the first occurrence would be.
+                true => event.occurrences(Some(query.now), query.now.checked_add_signed(Duration::nanoseconds(1))),
+                false => event.occurrences(query.from, window_end),
+            })
+            .filter(|occurrence| !query.ongoing || (occurrence.is_ongoing(query.now) && occurrence.overlaps(query.from, window_end)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Occurrences of a recurring event share its id, so the start time comes first.
+fn sort_key(occurrence: &Event) -> (DateTime<Utc>, Uuid) {
+    (occurrence.start_time, occurrence.get_id())
+}
+
+fn cursor(occurrence: &Event) -> String {
+    format!("{}_{}", occurrence.start_time.timestamp_millis(), occurrence.get_id())
+}
+
+fn parse_cursor(cursor: &str) -> Option<(DateTime<Utc>, Uuid)> {
+    let (millis, id) = cursor.split_once('_')?;
+    Some((DateTime::from_timestamp_millis(millis.parse().ok()?)?, id.parse().ok()?))
+}
+
+/// Longest window occurrences are listed for at once.
+pub const MAX_WINDOW_DAYS: i64 = 62;
+/// Window listed when a query has a `from` but no `to`.
+pub const DEFAULT_WINDOW_DAYS: i64 = 7;
+
+#[derive(Debug, Clone)]
+pub struct EventQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Case-insensitive substring of the title.
+    pub title: Option<String>,
+    pub tag: Option<String>,
+    pub category: Option<Category>,
+    /// Only occurrences running at `now`.
+    pub ongoing: bool,
+    pub now: DateTime<Utc>,
+    pub limit: usize,
+    /// Position after which the page starts, taken from a previous [`EventPage`].
+    pub cursor: Option<String>,
+    pub order: SortOrder,
+    /// Adds the times in this timezone to every returned occurrence.
+    pub timezone: Option<Tz>,
+}
+
+impl Default for EventQuery {
+    fn default() -> Self {
+        EventQuery {
+            from: None,
+            to: None,
+            title: None,
+            tag: None,
+            category: None,
+            ongoing: false,
+            now: Utc::now(),
+            limit: usize::MAX,
+            cursor: None,
+            order: SortOrder::Asc,
+            timezone: None,
+        }
+    }
+}
+
+impl EventQuery {
+    /// End of the listed window: `to`, or [`DEFAULT_WINDOW_DAYS`] after `from` without one if
+    /// that is representable.
+    pub fn window_end(&self) -> Option<DateTime<Utc>> {
+        self.to.or_else(|| self.from.and_then(|from| from.checked_add_signed(Duration::days(DEFAULT_WINDOW_DAYS))))
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        self.title.as_ref().is_none_or(|title| event.title().to_lowercase().contains(&title.to_lowercase()))
+            && self.tag.as_ref().is_none_or(|tag| event.has_tag(tag))
+            && self.category.is_none_or(|category| event.category() == category)
+    }
+
+    /// Sorts `occurrences` and returns the page following the cursor.
+    pub fn page(&self, mut occurrences: Vec<Event>) -> EventPage {
+        occurrences.sort_by_key(sort_key);
+        if self.order == SortOrder::Desc {
+            occurrences.reverse();
+        }
+
+        if let Some(after) = self.cursor.as_deref().and_then(parse_cursor) {
+            occurrences.retain(|occurrence| match self.order {
+                SortOrder::Asc => sort_key(occurrence) > after,
+                SortOrder::Desc => sort_key(occurrence) < after,
+            });
+        }
+
+        let next_cursor = (occurrences.len() > self.limit)
+            .then(|| cursor(&occurrences[self.limit - 1]));
+        occurrences.truncate(self.limit);
+        if let Some(timezone) = self.timezone {
+            occurrences = occurrences.into_iter().map(|occurrence| occurrence.localized(timezone)).collect();
+        }
+        EventPage { events: occurrences, next_cursor }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.limit == 0 {
+            return Err("limit must be at least 1".to_string());
+        }
+        // Recurring events are expanded for the whole window, so it needs both ends and a bound.
+        match (self.from, self.to) {
+            (None, Some(_)) => return Err("from is required with to".to_string()),
+            (Some(from), Some(to)) if from >= to => return Err("from must be before to".to_string()),
+            (Some(from), Some(to)) if to - from > Duration::days(MAX_WINDOW_DAYS) => {
+                return Err(format!("from and to must not be more than {} days apart", MAX_WINDOW_DAYS));
+            }
+            (Some(_), None) if self.window_end().is_none() => {
+                return Err(format!("from must be at least {} days before the last representable time", DEFAULT_WINDOW_DAYS));
+            }
+            _ => {}
+        }
+        if self.cursor.as_deref().is_some_and(|cursor| parse_cursor(cursor).is_none()) {
+            return Err("Invalid cursor".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct EventPage {
+    pub events: Vec<Event>,
+    pub next_cursor: Option<String>,
 }
\ No newline at end of file
@@ -1,17 +1,70 @@
 
 use std::collections::HashMap;
 use std::fmt;
-use chrono::{Duration, Utc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex, RwLock};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use log::{error, info, warn};
+use serde::de::Error;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
-use crate::calendar::calendar::Calendar;
+use crate::calendar::calendar::{Access, Calendar, CalendarSummary, Event, EventQuery, Visibility};
 use crate::calendar::skyblock;
+use crate::auth;
+use crate::config::data_file;
+use crate::health::{HEALTH, SCHEDULER};
+use crate::helpers::{read_json_from_file, write_json_to_file};
+use crate::metrics::METRICS;
+use crate::notifications::check_remind;
+use crate::shutdown::ShutdownSignal;
+use crate::snapshots::{Snapshot, SNAPSHOTS};
+
+const USERS_FILE: &str = "users.json";
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// A calendar of another user followed by this one.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct Subscription {
+    pub calendar: Uuid,
+    /// Seconds before each event to be reminded, instead of the event's own setting.
+    #[serde(default)]
+    pub remind: Option<i64>,
+}
+
+/// Settings a user chooses for themselves.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct Preferences {
+    /// IANA timezone used for the agenda and as the default for new events.
+    #[serde(default)]
+    #[schema(value_type = Option<String>, example = "Europe/Berlin")]
+    pub timezone: Option<Tz>,
+}
+
+/// A user without their events, as listed to admins.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct UserSummary {
+    pub id: Uuid,
+    pub name: String,
+    /// False for the generated global user and users created before accounts had passwords.
+    pub can_log_in: bool,
+    pub calendars: Vec<CalendarSummary>,
+    pub subscriptions: usize,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
     id:Uuid,
     name: String,
     calendars: HashMap<Uuid, Calendar>,
+    #[serde(default)]
+    subscriptions: HashMap<Uuid, Subscription>,
+    /// Argon2 hash; users without one cannot log in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password_hash: Option<String>,
+    #[serde(default)]
+    preferences: Preferences,
 }
 
 impl fmt::Display for User {
@@ -26,46 +79,139 @@ impl fmt::Display for User {
 }
 impl User {
     pub fn new(name: String) -> Self {
-        User { id: Uuid::new_v4(), name, calendars: HashMap::new() }
+        User { id: Uuid::new_v4(), name, calendars: HashMap::new(), subscriptions: HashMap::new(), password_hash: None, preferences: Preferences::default() }
     }
 
+    #[allow(dead_code)]
     pub fn get_id(&self) -> Uuid {
-        self.id.clone()
-    }
-
-    pub fn get_name(&self) -> String {
-        self.name.clone()
+        self.id
     }
 
-    pub fn add_calendar(&mut self, calendar: Calendar) {
-        self.calendars.insert(Uuid::new_v4(), calendar);
+    pub fn add_calendar(&mut self, calendar: Calendar) -> Uuid {
+        let id = *calendar.get_id();
+        self.calendars.insert(id, calendar);
+        id
     }
 
+    #[allow(dead_code)]
     pub fn get_calendar(&self, id: &Uuid) -> Option<&Calendar> {
-        self.calendars.get(&id)
+        self.calendars.get(id)
     }
     pub fn list_calendars(&self) -> Vec<&Calendar> {
         self.calendars.values().collect::<Vec<&Calendar>>()
     }
+    pub fn list_subscriptions(&self) -> Vec<&Subscription> {
+        self.subscriptions.values().collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DatabaseError {
+    UserNotFound(Uuid),
+    CalendarNotFound(Uuid),
+    /// The user lacks the access needed for the operation.
+    Forbidden(Access),
+    Invalid(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::UserNotFound(id) => write!(f, "No user with id {}", id),
+            DatabaseError::CalendarNotFound(id) => write!(f, "No calendar with id {}", id),
+            DatabaseError::Forbidden(Access::Read) => write!(f, "You cannot read this calendar"),
+            DatabaseError::Forbidden(Access::Write) => write!(f, "You cannot modify this calendar"),
+            DatabaseError::Forbidden(Access::Owner) => write!(f, "Only the owner can do this"),
+            DatabaseError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
 }
 
+const GLOBAL_USER: &str = "GLOBAL";
+
+/// How far ahead the Skyblock calendar is generated.
+pub const CALENDAR_SPAN_MINUTES: i64 = 7460;
+
+pub static DATABASE: LazyLock<RwLock<DataBase>> = LazyLock::new(|| RwLock::new(DataBase::new()));
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DataBase {
-    users: HashMap<Uuid, User>
+    users: HashMap<Uuid, User>,
+    /// Why an existing users file could not be loaded. Saving is refused then, so the
+    /// accounts in it are not overwritten with the empty set that was loaded instead.
+    #[serde(skip)]
+    load_error: Option<String>,
+}
+
+/// Numbers snapshots in the order they were taken, so an older one never overwrites a newer one.
+static SNAPSHOT_GENERATION: AtomicU64 = AtomicU64::new(0);
+/// Generation of the last snapshot written; also serialises the writes.
+static SAVED_GENERATION: Mutex<u64> = Mutex::new(0);
+
+/// The users to persist, taken while the lock is held and written after it is released.
+pub struct UsersSnapshot {
+    generation: u64,
+    users: HashMap<Uuid, User>,
 }
+
+impl UsersSnapshot {
+    /// Writes the users unless a later snapshot was written already. Blocks on the file system,
+    /// so call it off the async runtime.
+    pub fn save(self) -> Result<(), serde_json::Error> {
+        let mut saved = SAVED_GENERATION.lock().unwrap();
+        if *saved > self.generation {
+            return Ok(());
+        }
+        write_json_to_file(data_file(USERS_FILE), &self.users)?;
+        *saved = self.generation;
+        info!("Saved {} users to {}", self.users.len(), USERS_FILE);
+        Ok(())
+    }
+}
+
 impl DataBase {
     pub fn new() -> Self {
-        DataBase { users: HashMap::new() }.init()
+        let path = data_file(USERS_FILE);
+        let (users, load_error) = match read_json_from_file(&path) {
+            Ok(users) => (users, None),
+            Err(e) if !path.exists() => {
+                warn!("No users loaded, could not read {}: {}", USERS_FILE, e);
+                (HashMap::new(), None)
+            }
+            Err(e) => {
+                error!("Could not load the users from {}: {}", USERS_FILE, e);
+                (HashMap::new(), Some(e.to_string()))
+            }
+        };
+        DataBase { users, load_error }.init()
+    }
+    /// Why the existing users file could not be loaded, if it couldn't.
+    pub fn load_error(&self) -> Option<&str> {
+        self.load_error.as_deref()
     }
     fn init(mut self) -> Self {
-        let mut global_user = User::new("GLOBAL".to_string());
-        let skyblock = skyblock::generate_calendar(Utc::now(), Utc::now() + Duration::minutes(7460));
+        self.refresh_skyblock_calendar();
+        self
+    }
+    /// Regenerates the Skyblock calendar from now, e.g. after new contest crops were imported.
+    pub fn refresh_skyblock_calendar(&mut self) {
+        let now = Utc::now();
+        self.regenerate_skyblock_calendar(now, now + Duration::minutes(CALENDAR_SPAN_MINUTES));
+    }
+    /// Replaces the Skyblock calendar with the events between `from` and `to`.
+    pub fn regenerate_skyblock_calendar(&mut self, from: DateTime<Utc>, to: DateTime<Utc>) {
+        let global_id = skyblock::stable_id(GLOBAL_USER);
+        self.users.remove(&global_id);
+        let mut global_user = User { id: global_id, ..User::new(GLOBAL_USER.to_string()) };
+        let skyblock = skyblock::generate_calendar(from, to).with_visibility(Visibility::Public);
         global_user.add_calendar(skyblock);
         self.add_user(global_user);
-        self
+        SNAPSHOTS.changed(Snapshot::Calendar);
     }
-    pub fn add_user(&mut self, user: User) {
-        self.users.insert(user.id, user);
+    pub fn add_user(&mut self, user: User) -> Uuid {
+        let id = user.id;
+        self.users.insert(id, user);
+        id
     }
     pub fn get_user(&self, user_id:Uuid) -> Option<&User> {
         self.users.get(&user_id)
@@ -73,6 +219,277 @@ impl DataBase {
     pub fn list_users(&self) -> Vec<&User> {
         self.users.values().collect()
     }
+
+    pub fn user_summaries(&self) -> Vec<UserSummary> {
+        self.users.values()
+            .map(|user| UserSummary {
+                id: user.id,
+                name: user.name.clone(),
+                can_log_in: user.password_hash.is_some(),
+                calendars: user.calendars.values().map(Calendar::summary).collect(),
+                subscriptions: user.subscriptions.len(),
+            })
+            .collect()
+    }
+
+    /// Deletes a user with their calendars. The generated global user cannot be removed.
+    pub fn remove_user(&mut self, user_id: Uuid) -> Result<User, DatabaseError> {
+        if user_id == skyblock::stable_id(GLOBAL_USER) {
+            return Err(DatabaseError::Invalid("The generated Skyblock user cannot be removed".to_string()));
+        }
+        let user = self.users.remove(&user_id).ok_or(DatabaseError::UserNotFound(user_id))?;
+        SNAPSHOTS.changed(Snapshot::Calendar);
+        Ok(user)
+    }
+
+    /// Changes a calendar's title, description or visibility regardless of who owns it. The
+    /// Skyblock calendar is regenerated every few minutes, so it cannot be edited.
+    pub fn edit_calendar(
+        &mut self,
+        calendar_id: Uuid,
+        title: Option<String>,
+        description: Option<String>,
+        visibility: Option<Visibility>,
+    ) -> Result<CalendarSummary, DatabaseError> {
+        if calendar_id == skyblock::calendar_id() {
+            return Err(DatabaseError::Invalid("The generated Skyblock calendar cannot be edited".to_string()));
+        }
+        if title.as_deref().is_some_and(|title| title.trim().is_empty()) {
+            return Err(DatabaseError::Invalid("title must not be empty".to_string()));
+        }
+        let calendar = self.find_calendar_mut(calendar_id).ok_or(DatabaseError::CalendarNotFound(calendar_id))?;
+        calendar.edit(title, description, visibility);
+        SNAPSHOTS.changed(Snapshot::Calendar);
+        Ok(calendar.summary())
+    }
+
+    /// Whether there are users besides the generated global one.
+    pub fn has_accounts(&self) -> bool {
+        self.users.keys().any(|&id| id != skyblock::stable_id(GLOBAL_USER))
+    }
+
+    /// Every user except the generated global one, to be saved once the lock is released.
+    pub fn snapshot(&self) -> Result<UsersSnapshot, serde_json::Error> {
+        if let Some(e) = &self.load_error {
+            return Err(serde_json::Error::custom(format!("{} could not be loaded, so it is not overwritten: {}", USERS_FILE, e)));
+        }
+        let users = self.users.iter()
+            .filter(|(&id, _)| id != skyblock::stable_id(GLOBAL_USER))
+            .map(|(&id, user)| (id, user.clone()))
+            .collect();
+        Ok(UsersSnapshot { generation: SNAPSHOT_GENERATION.fetch_add(1, Ordering::Relaxed) + 1, users })
+    }
+
+    /// Saves every user except the generated global one.
+    pub fn save(&self) -> Result<(), serde_json::Error> {
+        self.snapshot()?.save()
+    }
+
+    /// Checks a new account's name and password, returning the name to register it under.
+    pub fn check_registration(&self, name: &str, password: &str) -> Result<String, DatabaseError> {
+        let length = password.chars().count();
+        if length < MIN_PASSWORD_LENGTH {
+            return Err(DatabaseError::Invalid(format!("password must be at least {} characters", MIN_PASSWORD_LENGTH)));
+        }
+        if length > auth::MAX_PASSWORD_LENGTH {
+            return Err(DatabaseError::Invalid(format!("password must be at most {} characters", auth::MAX_PASSWORD_LENGTH)));
+        }
+        self.check_name(name).map(str::to_string)
+    }
+
+    fn check_name<'a>(&self, name: &'a str) -> Result<&'a str, DatabaseError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(DatabaseError::Invalid("name must not be empty".to_string()));
+        }
+        if name.eq_ignore_ascii_case(GLOBAL_USER) || self.users.values().any(|user| user.name.eq_ignore_ascii_case(name)) {
+            return Err(DatabaseError::Invalid(format!("The name '{}' is taken", name)));
+        }
+        Ok(name)
+    }
+
+    /// Creates an account that logs in with the password `password_hash` was made from.
+    pub fn register(&mut self, name: &str, password_hash: String) -> Result<Uuid, DatabaseError> {
+        // Checked again: the name may have been taken while the password was hashed.
+        let name = self.check_name(name)?;
+        let user = User { password_hash: Some(password_hash), ..User::new(name.to_string()) };
+        Ok(self.add_user(user))
+    }
+
+    /// Id and password hash of the user called `name`, to check a login against outside the lock.
+    pub fn credentials(&self, name: &str) -> Option<(Uuid, Option<String>)> {
+        self.users.values()
+            .find(|user| user.name.eq_ignore_ascii_case(name.trim()))
+            .map(|user| (user.id, user.password_hash.clone()))
+    }
+
+    fn user(&self, user_id: Uuid) -> Result<&User, DatabaseError> {
+        self.users.get(&user_id).ok_or(DatabaseError::UserNotFound(user_id))
+    }
+
+    fn user_mut(&mut self, user_id: Uuid) -> Result<&mut User, DatabaseError> {
+        self.users.get_mut(&user_id).ok_or(DatabaseError::UserNotFound(user_id))
+    }
+
+    /// The calendar with `calendar_id` and the id of its owner.
+    pub fn find_calendar(&self, calendar_id: Uuid) -> Option<(Uuid, &Calendar)> {
+        self.users.values()
+            .find_map(|user| user.calendars.get(&calendar_id).map(|calendar| (user.id, calendar)))
+    }
+
+    fn find_calendar_mut(&mut self, calendar_id: Uuid) -> Option<&mut Calendar> {
+        self.users.values_mut().find_map(|user| user.calendars.get_mut(&calendar_id))
+    }
+
+    pub fn public_calendars(&self) -> Vec<&Calendar> {
+        self.users.values()
+            .flat_map(|user| user.calendars.values())
+            .filter(|calendar| calendar.visibility() == Visibility::Public)
+            .collect()
+    }
+
+    /// Highest access `user_id` has to the calendar, or `None` when it cannot even read it.
+    pub fn access(&self, user_id: Uuid, calendar_id: Uuid) -> Result<Option<Access>, DatabaseError> {
+        let (owner, calendar) = self.find_calendar(calendar_id).ok_or(DatabaseError::CalendarNotFound(calendar_id))?;
+        if owner == user_id {
+            return Ok(Some(Access::Owner));
+        }
+        Ok(calendar.shared_access(&user_id))
+    }
+
+    fn require(&self, user_id: Uuid, calendar_id: Uuid, needed: Access) -> Result<(), DatabaseError> {
+        match self.access(user_id, calendar_id)? {
+            Some(access) if access >= needed => Ok(()),
+            _ => Err(DatabaseError::Forbidden(needed)),
+        }
+    }
+
+    pub fn create_calendar(&mut self, user_id: Uuid, calendar: Calendar) -> Result<Uuid, DatabaseError> {
+        let id = self.user_mut(user_id)?.add_calendar(calendar);
+        SNAPSHOTS.changed(Snapshot::Calendar);
+        Ok(id)
+    }
+
+    pub fn add_event(&mut self, user_id: Uuid, calendar_id: Uuid, event: Event) -> Result<Uuid, DatabaseError> {
+        self.require(user_id, calendar_id, Access::Write)?;
+        let calendar = self.find_calendar_mut(calendar_id).ok_or(DatabaseError::CalendarNotFound(calendar_id))?;
+        let id = calendar.add_event(event);
+        SNAPSHOTS.changed(Snapshot::Calendar);
+        Ok(id)
+    }
+
+    /// Shares the calendar with `with`, or stops sharing it when `access` is `None`. Owner only.
+    pub fn share(&mut self, user_id: Uuid, calendar_id: Uuid, with: Uuid, access: Option<Access>) -> Result<(), DatabaseError> {
+        self.require(user_id, calendar_id, Access::Owner)?;
+        self.user(with)?;
+        if with == user_id || access == Some(Access::Owner) {
+            return Err(DatabaseError::Invalid("A calendar can only be shared read or write with other users".to_string()));
+        }
+        let calendar = self.find_calendar_mut(calendar_id).ok_or(DatabaseError::CalendarNotFound(calendar_id))?;
+        match access {
+            Some(access) => calendar.share(with, access),
+            None => {
+                calendar.unshare(&with);
+            }
+        }
+        SNAPSHOTS.changed(Snapshot::Calendar);
+        Ok(())
+    }
+
+    pub fn subscribe(&mut self, user_id: Uuid, calendar_id: Uuid, remind: Option<i64>) -> Result<(), DatabaseError> {
+        self.require(user_id, calendar_id, Access::Read)?;
+        if self.user(user_id)?.calendars.contains_key(&calendar_id) {
+            return Err(DatabaseError::Invalid("Your own calendars are always in your agenda".to_string()));
+        }
+        if let Some(remind) = remind {
+            check_remind(remind).map_err(DatabaseError::Invalid)?;
+        }
+        self.user_mut(user_id)?.subscriptions.insert(calendar_id, Subscription { calendar: calendar_id, remind });
+        SNAPSHOTS.changed(Snapshot::Calendar);
+        Ok(())
+    }
+
+    pub fn preferences(&self, user_id: Uuid) -> Result<Preferences, DatabaseError> {
+        Ok(self.user(user_id)?.preferences.clone())
+    }
+
+    pub fn set_preferences(&mut self, user_id: Uuid, preferences: Preferences) -> Result<(), DatabaseError> {
+        self.user_mut(user_id)?.preferences = preferences;
+        SNAPSHOTS.changed(Snapshot::Calendar);
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, user_id: Uuid, calendar_id: Uuid) -> Result<bool, DatabaseError> {
+        let removed = self.user_mut(user_id)?.subscriptions.remove(&calendar_id).is_some();
+        if removed {
+            SNAPSHOTS.changed(Snapshot::Calendar);
+        }
+        Ok(removed)
+    }
+
+    /// Calendars `user_id` owns or can read through sharing or a public subscription.
+    pub fn visible_calendars(&self, user_id: Uuid) -> Result<Vec<(CalendarSummary, Access)>, DatabaseError> {
+        let user = self.user(user_id)?;
+        let owned = user.calendars.values().map(|calendar| (calendar.summary(), Access::Owner));
+        let shared = self.users.values()
+            .filter(|other| other.id != user_id)
+            .flat_map(|other| other.calendars.values())
+            .filter(|calendar| calendar.is_shared_with(&user_id) || user.subscriptions.contains_key(calendar.get_id()))
+            .filter_map(|calendar| calendar.shared_access(&user_id).map(|access| (calendar.summary(), access)));
+        Ok(owned.chain(shared).collect())
+    }
+
+    /// Occurrences of `calendar` matching `query`. The Skyblock calendar is only stored for the
+    /// next few days, so it is generated for the queried range instead.
+    pub fn occurrences(&self, calendar: &Calendar, query: &EventQuery) -> Vec<Event> {
+        let (Some(from), Some(to)) = (query.from, query.window_end()) else {
+            return calendar.occurrences(query);
+        };
+        if *calendar.get_id() != skyblock::calendar_id() {
+            return calendar.occurrences(query);
+        }
+        skyblock::generate_calendar(from, to).occurrences(query)
+    }
+
+    /// Whether `viewer` can read every calendar with events taking up `user_id`'s time, so that
+    /// their free/busy is complete.
+    pub fn sees_busy_times(&self, user_id: Uuid, viewer: Uuid) -> Result<bool, DatabaseError> {
+        let user = self.user(user_id)?;
+        let subscribed = user.subscriptions.values()
+            .filter(|subscription| self.require(user_id, subscription.calendar, Access::Read).is_ok())
+            .filter_map(|subscription| self.find_calendar(subscription.calendar).map(|(_, calendar)| calendar));
+        Ok(user.calendars.values().chain(subscribed)
+            .filter(|calendar| calendar.list_events().iter().any(|event| event.takes_time()))
+            .all(|calendar| self.require(viewer, *calendar.get_id(), Access::Read).is_ok()))
+    }
+
+    /// Occurrences from the user's own calendars and subscriptions, with reminder overrides applied.
+    pub fn agenda(&self, user_id: Uuid, query: &EventQuery) -> Result<Vec<Event>, DatabaseError> {
+        self.agenda_seen_by(user_id, user_id, query)
+    }
+
+    /// The part of `user_id`'s agenda coming from calendars `viewer` can read as well.
+    pub fn agenda_seen_by(&self, user_id: Uuid, viewer: Uuid, query: &EventQuery) -> Result<Vec<Event>, DatabaseError> {
+        let user = self.user(user_id)?;
+        let readable = |calendar_id: Uuid| self.require(viewer, calendar_id, Access::Read).is_ok();
+        let mut occurrences: Vec<Event> = user.calendars.values()
+            .filter(|calendar| readable(*calendar.get_id()))
+            .flat_map(|calendar| self.occurrences(calendar, query))
+            .collect();
+
+        for subscription in user.subscriptions.values() {
+            // Subscriptions stay around when a calendar is unshared or deleted, but show nothing.
+            if self.require(user_id, subscription.calendar, Access::Read).is_err() || !readable(subscription.calendar) {
+                continue;
+            }
+            let Some((_, calendar)) = self.find_calendar(subscription.calendar) else { continue };
+            occurrences.extend(self.occurrences(calendar, query).into_iter().map(|event| match subscription.remind {
+                Some(remind) => event.with_remind(remind),
+                None => event,
+            }));
+        }
+        Ok(occurrences)
+    }
 }
 
 impl fmt::Display for DataBase {
@@ -87,3 +504,21 @@ impl fmt::Display for DataBase {
         write!(f, "Database with users: [{}]", user_list)
     }
 }
+
+/// Regenerates the Skyblock calendar every Skyblock day, so it keeps covering the next
+/// [`CALENDAR_SPAN_MINUTES`] however long the server runs.
+pub async fn schedule_skyblock_calendar(mut shutdown: ShutdownSignal) {
+    let _running = HEALTH.poller_started(SCHEDULER);
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(skyblock::SECONDS_PER_DAY as u64));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.recv() => {
+                info!("Calendar scheduler stopped");
+                return;
+            }
+        }
+        DATABASE.write().unwrap().refresh_skyblock_calendar();
+        METRICS.poller_succeeded(SCHEDULER);
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, RwLock};
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::calendar::database::DATABASE;
+use crate::calendar::event::{Category, Event};
+use crate::calendar::mayor::{Mayor, Perk, PerkEffect};
+use crate::calendar::skyblock::{event_id, EventRule, Schedule, SkyblockDateTime, REMIND_BEFORE, SECONDS_PER_DAY};
+use crate::config::data_file;
+use crate::health::HEALTH;
+use crate::helpers::{hypixel_get, read_json_from_file, write_json_to_file};
+use crate::metrics::METRICS;
+use crate::shutdown::ShutdownSignal;
+
+const ELECTION_PATH: &str = "v2/resources/skyblock/election";
+/// Raw election resources keyed by the year the mayor in office was elected.
+const SNAPSHOTS_FILE: &str = "election.json";
+/// Vote counts of running elections, collected by [`poll_elections`].
+const VOTES_FILE: &str = "election_votes.json";
+
+/// Vote counts are kept at most once per Skyblock day, so an election keeps about a hundred.
+const VOTE_SAMPLE_SECS: i64 = SECONDS_PER_DAY;
+
+/// Mayors take office when the election booth closes, on Late Spring 27th.
+const TERM_START: (i8, i8) = (3, 27);
+
+pub static ELECTION_HISTORY: LazyLock<RwLock<ElectionHistory>> = LazyLock::new(|| {
+    let snapshots = read_json_from_file(data_file(SNAPSHOTS_FILE)).unwrap_or_else(|e| {
+        warn!("Starting without election history, could not read {}: {}", SNAPSHOTS_FILE, e);
+        BTreeMap::new()
+    });
+    let votes = read_json_from_file(data_file(VOTES_FILE)).unwrap_or_default();
+    RwLock::new(ElectionHistory { snapshots, votes })
+});
+
+/// The Hypixel `resources/skyblock/election` response.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ElectionResource {
+    pub success: bool,
+    pub last_updated: i64,
+    pub mayor: MayorResource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current: Option<ElectionRound>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MayorResource {
+    pub key: String,
+    pub name: String,
+    pub perks: Vec<PerkResource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minister: Option<MinisterResource>,
+    pub election: ElectionRound,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MinisterResource {
+    pub key: String,
+    pub name: String,
+    pub perk: PerkResource,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct PerkResource {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub minister: bool,
+}
+
+impl PerkResource {
+    fn perk(&self) -> Option<Perk> {
+        let perk = Perk::from_api(&self.name, &self.description);
+        if perk.is_none() {
+            debug!("Ignoring unknown perk '{}'", self.name);
+        }
+        perk
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ElectionRound {
+    pub year: i16,
+    pub candidates: Vec<Candidate>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Candidate {
+    pub key: String,
+    pub name: String,
+    pub perks: Vec<PerkResource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub votes: Option<u64>,
+}
+
+/// Vote counts of every candidate at one point in time.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VotePoll {
+    pub at: DateTime<Utc>,
+    pub votes: BTreeMap<String, u64>,
+}
+
+impl VotePoll {
+    /// Each candidate's share of the votes cast so far.
+    pub fn shares(&self) -> BTreeMap<String, f64> {
+        let total: u64 = self.votes.values().sum();
+        self.votes.iter()
+            .map(|(name, votes)| (name.clone(), if total == 0 { 0.0 } else { *votes as f64 / total as f64 }))
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct CandidateStanding {
+    pub name: String,
+    pub perks: Vec<PerkResource>,
+    pub votes: Option<u64>,
+    pub share: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ShareSample {
+    pub at: DateTime<Utc>,
+    pub shares: BTreeMap<String, f64>,
+}
+
+/// One election: its candidates, the result once known and how the votes moved.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ElectionReport {
+    pub year: i16,
+    pub result: Option<Election>,
+    pub prediction: Option<Election>,
+    pub candidates: Vec<CandidateStanding>,
+    pub vote_share: Vec<ShareSample>,
+}
+
+fn standings(round: &ElectionRound) -> Vec<CandidateStanding> {
+    let total: u64 = round.candidates.iter().filter_map(|candidate| candidate.votes).sum();
+    let mut standings: Vec<CandidateStanding> = round.candidates.iter()
+        .map(|candidate| CandidateStanding {
+            name: candidate.name.clone(),
+            perks: candidate.perks.clone(),
+            votes: candidate.votes,
+            share: candidate.votes.filter(|_| total > 0).map(|votes| votes as f64 / total as f64),
+        })
+        .collect();
+    standings.sort_by_key(|standing| std::cmp::Reverse(standing.votes));
+    standings
+}
+
+/// Predicts the outcome of a running election from the current vote counts.
+///
+/// The leading candidate becomes mayor with all their perks and the runner-up
+/// becomes minister with their minister perk.
+fn predict(round: &ElectionRound) -> Option<Election> {
+    let mut candidates: Vec<&Candidate> = round.candidates.iter().filter(|c| c.votes.is_some()).collect();
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.votes));
+    let winner = candidates.first()?;
+    let runner_up = candidates.get(1);
+
+    let mut perks: Vec<Perk> = winner.perks.iter().filter_map(PerkResource::perk).collect();
+    perks.extend(runner_up.into_iter()
+        .flat_map(|candidate| candidate.perks.iter().filter(|perk| perk.minister))
+        .filter_map(PerkResource::perk));
+
+    Some(Election {
+        year: round.year,
+        mayor: winner.name.parse().ok()?,
+        minister: runner_up.and_then(|candidate| candidate.name.parse().ok()),
+        perks,
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct ElectionHistory {
+    snapshots: BTreeMap<i16, ElectionResource>,
+    votes: BTreeMap<i16, Vec<VotePoll>>,
+}
+
+/// What recording an election resource changed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Recorded {
+    /// A mayor that was not known before took office.
+    pub new_mayor: bool,
+    /// Anything that is saved changed.
+    pub changed: bool,
+}
+
+impl ElectionHistory {
+    /// Stores a freshly fetched resource. A vote sample is kept only when the tally moved and
+    /// the previous one is at least [`VOTE_SAMPLE_SECS`] old.
+    pub fn record(&mut self, resource: ElectionResource, at: DateTime<Utc>) -> Recorded {
+        let mut recorded = Recorded::default();
+        if let Some(current) = &resource.current {
+            let votes: BTreeMap<String, u64> = current.candidates.iter()
+                .filter_map(|candidate| Some((candidate.name.clone(), candidate.votes?)))
+                .collect();
+            let polls = self.votes.entry(current.year).or_default();
+            let due = polls.last().is_none_or(|last| {
+                last.votes != votes && at - last.at >= Duration::seconds(VOTE_SAMPLE_SECS)
+            });
+            if !votes.is_empty() && due {
+                polls.push(VotePoll { at, votes });
+                recorded.changed = true;
+            }
+        }
+        let year = resource.mayor.election.year;
+        match self.snapshots.get(&year) {
+            Some(known) if known.mayor == resource.mayor && known.current == resource.current => {}
+            known => {
+                recorded.new_mayor = known.is_none();
+                recorded.changed = true;
+                self.snapshots.insert(year, resource);
+            }
+        }
+        recorded
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn save(&self) -> Result<(), serde_json::Error> {
+        write_json_to_file(data_file(SNAPSHOTS_FILE), &self.snapshots)?;
+        write_json_to_file(data_file(VOTES_FILE), &self.votes)
+    }
+
+    /// Every known mayor, followed by the predicted winner of the running election.
+    pub fn elections(&self) -> Vec<Election> {
+        let mut elections: Vec<Election> = self.snapshots.values()
+            .filter_map(|snapshot| Election::from_mayor(&snapshot.mayor))
+            .collect();
+        if let Some(prediction) = self.current().and_then(predict) {
+            elections.push(prediction);
+        }
+        elections
+    }
+
+    /// The running election, if the latest snapshot has one.
+    pub fn current(&self) -> Option<&ElectionRound> {
+        self.snapshots.values().next_back()?.current.as_ref()
+    }
+
+    pub fn report(&self, year: i16) -> Option<ElectionReport> {
+        let elected = self.snapshots.get(&year);
+        let running = self.current().filter(|current| current.year == year);
+        let round = elected.map(|snapshot| &snapshot.mayor.election).or(running)?;
+
+        Some(ElectionReport {
+            year,
+            result: elected.and_then(|snapshot| Election::from_mayor(&snapshot.mayor)),
+            prediction: running.and_then(predict),
+            candidates: standings(round),
+            vote_share: self.votes.get(&year).into_iter().flatten()
+                .map(|poll| ShareSample { at: poll.at, shares: poll.shares() })
+                .collect(),
+        })
+    }
+
+    /// The mayor in office according to the latest snapshot.
+    pub fn mayor(&self) -> Option<Election> {
+        Election::from_mayor(&self.snapshots.values().next_back()?.mayor)
+    }
+
+    pub fn history(&self) -> Vec<Election> {
+        self.snapshots.values()
+            .filter_map(|snapshot| Election::from_mayor(&snapshot.mayor))
+            .collect()
+    }
+}
+
+/// Mayor and minister chosen by one election, with the perks they bring.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct Election {
+    /// Year the election was held in; the winner is in office during the following year.
+    pub year: i16,
+    #[schema(value_type = String, example = "Diana")]
+    pub mayor: Mayor,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub minister: Option<Mayor>,
+    #[schema(value_type = Vec<String>)]
+    pub perks: Vec<Perk>,
+}
+
+impl Election {
+    fn from_mayor(mayor: &MayorResource) -> Option<Election> {
+        let Ok(name) = mayor.name.parse() else {
+            warn!("Ignoring election {} won by unknown mayor '{}'", mayor.election.year, mayor.name);
+            return None;
+        };
+        let perks = mayor.perks.iter()
+            .chain(mayor.minister.as_ref().map(|minister| &minister.perk))
+            .filter_map(PerkResource::perk)
+            .collect();
+
+        Some(Election {
+            year: mayor.election.year,
+            mayor: name,
+            minister: mayor.minister.as_ref().and_then(|minister| minister.name.parse().ok()),
+            perks,
+        })
+    }
+
+    pub fn term_start(&self) -> SkyblockDateTime {
+        SkyblockDateTime::new(TERM_START.1, TERM_START.0, self.year + 1)
+    }
+
+    pub fn term_end(&self) -> SkyblockDateTime {
+        SkyblockDateTime::new(TERM_START.1, TERM_START.0, self.year + 2)
+    }
+
+    pub fn in_office(&self, day: &SkyblockDateTime) -> bool {
+        self.term_start() <= *day && *day < self.term_end()
+    }
+
+    fn effects(&self) -> impl Iterator<Item = (Perk, PerkEffect)> + '_ {
+        self.perks.iter().flat_map(|perk| perk.effects().into_iter().map(|effect| (*perk, effect)))
+    }
+
+    /// Applies every interval and duration change of the active perks to `rule`.
+    pub fn apply(&self, rule: &EventRule) -> EventRule {
+        let mut rule = *rule;
+        for (_, effect) in self.effects() {
+            match effect {
+                PerkEffect::Interval { rule: key, every_days } if key == rule.key => {
+                    rule.schedule = Schedule::EveryDays { every: every_days, offset: 0 };
+                }
+                PerkEffect::Duration { rule: key, duration } if key == rule.key => {
+                    rule.duration = duration;
+                }
+                _ => {}
+            }
+        }
+        rule
+    }
+
+    /// Events added by the active perks that start on `day`.
+    pub fn get_events(&self, day: &SkyblockDateTime) -> Vec<Event> {
+        let term_start = self.term_start();
+        self.effects()
+            .filter_map(|(perk, effect)| match effect {
+                PerkEffect::ExtraEvent { title, description, schedule, duration } if schedule.matches(day, &term_start) => {
+                    let start = day.start_of_day().as_datetime();
+                    let event = Event::new(
+                        title,
+                        description,
+                        start - Duration::seconds(REMIND_BEFORE),
+                        start,
+                        start + Duration::seconds(duration),
+                        duration,
+                        0,
+                        REMIND_BEFORE,
+                    );
+                    Some(event
+                        .with_id(event_id(perk.name(), start))
+                        .with_note(&format!("{}'s {} perk.", perk.mayor(), perk))
+                        .with_category(Category::MayorPerk)
+                        .with_tags(&["skyblock", "mayor"])
+                        .with_color(perk.mayor().color())
+                        .with_icon(&perk.mayor().icon()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+pub async fn fetch_election() -> Result<ElectionResource, reqwest::Error> {
+    hypixel_get(ELECTION_PATH)
+        .await?
+        .json::<ElectionResource>()
+        .await
+}
+
+/// Fetches the election once, records it and regenerates the calendar when the mayors changed.
+pub async fn refresh_election() -> Result<(), reqwest::Error> {
+    let resource = fetch_election().await?;
+    METRICS.poller_succeeded("election");
+
+    let (recorded, mayors_changed) = {
+        let mut history = ELECTION_HISTORY.write().unwrap();
+        let before = history.elections();
+        let recorded = history.record(resource, Utc::now());
+        (recorded, before != history.elections())
+    };
+    if recorded.new_mayor {
+        info!("Recorded the election of a new mayor");
+    }
+    if recorded.changed {
+        let saved = tokio::task::spawn_blocking(|| ELECTION_HISTORY.read().unwrap().save()).await;
+        if let Ok(Err(e)) = saved {
+            error!("Failed to save the election history: {}", e);
+        }
+    }
+    if mayors_changed {
+        DATABASE.write().unwrap().refresh_skyblock_calendar();
+    }
+    Ok(())
+}
+
+/// Records the election resource every `every`, regenerating the Skyblock
+/// calendar when a new mayor takes office or the predicted winner changes.
+/// Returns once `shutdown` fires, after finishing a poll that is in flight.
+pub async fn poll_elections(every: std::time::Duration, mut shutdown: ShutdownSignal) {
+    let _running = HEALTH.poller_started("election");
+    let mut interval = tokio::time::interval(every);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.recv() => {
+                info!("Election poller stopped");
+                return;
+            }
+        }
+        if let Err(e) = refresh_election().await {
+            error!("Failed to fetch the election: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::skyblock::{RULES, SECONDS_PER_DAY};
+
+    fn election(mayor: Mayor, perks: Vec<Perk>) -> Election {
+        Election { year: 402, mayor, minister: None, perks }
+    }
+
+    fn rule(key: &str) -> &'static EventRule {
+        RULES.iter().find(|rule| rule.key == key).unwrap()
+    }
+
+    #[test]
+    fn perks_change_only_their_rules() {
+        let scorpius = election(Mayor::Scorpius, vec![Perk::DarkerAuctions]);
+        assert_eq!(scorpius.apply(rule("dark_auction")).duration, 2 * SECONDS_PER_DAY);
+        assert_eq!(scorpius.apply(rule("travelling_zoo")).duration, rule("travelling_zoo").duration);
+        assert_eq!(election(Mayor::Scorpius, Vec::new()).apply(rule("dark_auction")).duration, rule("dark_auction").duration);
+    }
+
+    #[test]
+    fn perks_add_events_while_in_office() {
+        let marina = election(Mayor::Marina, vec![Perk::FishingFestival]);
+        let day = SkyblockDateTime::new(1, 4, 403);
+        assert!(marina.in_office(&day));
+        assert!(!marina.in_office(&marina.term_end()));
+
+        let events = marina.get_events(&day);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].title(), "Fishing Festival");
+        assert_eq!(events[0].start_time, day.as_datetime());
+        assert_eq!(events[0].category(), Category::MayorPerk);
+        assert_eq!(events[0].get_id(), marina.get_events(&day)[0].get_id());
+        assert!(marina.get_events(&SkyblockDateTime::new(2, 4, 403)).is_empty());
+    }
+
+    fn resource(last_updated: i64, votes: u64) -> ElectionResource {
+        serde_json::from_value(serde_json::json!({
+            "success": true,
+            "lastUpdated": last_updated,
+            "mayor": {
+                "key": "fishing", "name": "Marina", "perks": [],
+                "election": { "year": 402, "candidates": [] },
+            },
+            "current": {
+                "year": 403,
+                "candidates": [{ "key": "fishing", "name": "Marina", "perks": [], "votes": votes }],
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn only_changes_are_recorded() {
+        let now = Utc::now();
+        let mut history = ElectionHistory::default();
+        assert_eq!(history.record(resource(1, 10), now), Recorded { new_mayor: true, changed: true });
+        assert_eq!(history.record(resource(2, 10), now + Duration::hours(1)), Recorded::default());
+
+        // New tallies are sampled once per Skyblock day, but still update the running election.
+        let soon = now + Duration::seconds(VOTE_SAMPLE_SECS - 1);
+        assert_eq!(history.record(resource(3, 20), soon), Recorded { new_mayor: false, changed: true });
+        assert!(history.record(resource(4, 30), now + Duration::seconds(VOTE_SAMPLE_SECS)).changed);
+        assert_eq!(history.report(403).unwrap().vote_share.len(), 2);
+    }
+}
@@ -1,9 +1,28 @@
 use std::fmt;
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, FixedOffset, TimeZone, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::calendar::jacob::Crop;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// What kind of event this is, used to group and style events.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    /// Recurring events every Skyblock player sees.
+    Skyblock,
+    /// Events only happening because of the current mayor or minister.
+    MayorPerk,
+    #[default]
+    Personal,
+    Guild,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Event {
+    #[serde(default = "Uuid::new_v4")]
+    id: Uuid,
     title: String,
     description: String,
     notify_at: DateTime<Utc>,
@@ -11,7 +30,36 @@ pub struct Event {
     end_time: DateTime<Utc>,
     duration: i64,
     recurrence: i64,
-    remind: i64
+    remind: i64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    crops: Vec<Crop>,
+    #[serde(default)]
+    category: Category,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// CSS colour, e.g. `#AA00AA`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    /// IANA timezone whose wall-clock time a recurring event keeps across daylight saving time.
+    /// Generated Skyblock events have none and recur in fixed UTC steps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, example = "Europe/Berlin")]
+    timezone: Option<Tz>,
+    /// The times in the timezone asked for with `tz`; only in responses, never stored.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    local: Option<LocalTimes>,
+}
+
+/// An event's times with the offset of a timezone, e.g. `2026-03-29T20:00:00+02:00`.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct LocalTimes {
+    #[schema(value_type = String, example = "Europe/Berlin")]
+    timezone: Tz,
+    notify_at: DateTime<FixedOffset>,
+    start_time: DateTime<FixedOffset>,
+    end_time: DateTime<FixedOffset>,
 }
 
 impl fmt::Display for Event {
@@ -21,30 +69,264 @@ impl fmt::Display for Event {
 }
 
 impl Event {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(title: String, description:String, notify_at:DateTime<Utc>, start_time: DateTime<Utc>, end_time: DateTime<Utc>, duration:i64, recurrence:i64, remind:i64) -> Self {
-        Event { title, description, notify_at, start_time, end_time, duration, recurrence, remind}
+        Event { id: Uuid::new_v4(), title, description, notify_at, start_time, end_time, duration, recurrence, remind, crops: Vec::new(), category: Category::default(), tags: Vec::new(), color: None, icon: None, timezone: None, local: None }
+    }
+
+    /// Replaces the random id, for events that are regenerated and must keep their identity.
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn get_id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Attaches the crops of a Jacob's Farming Contest.
+    pub fn with_crops(mut self, crops: Vec<Crop>) -> Self {
+        self.crops = crops;
+        self
+    }
+
+    pub fn with_tags(mut self, tags: &[&str]) -> Self {
+        self.tags.extend(tags.iter().map(|tag| tag.to_string()));
+        self
+    }
+
+    pub fn with_category(mut self, category: Category) -> Self {
+        self.category = category;
+        self
+    }
+
+    pub fn with_color(mut self, color: &str) -> Self {
+        self.color = Some(color.to_string());
+        self
+    }
+
+    pub fn with_icon(mut self, icon: &str) -> Self {
+        self.icon = Some(icon.to_string());
+        self
+    }
+
+    /// Makes the event recur at the same wall-clock time in `timezone`.
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = Some(timezone);
+        self
+    }
+
+    /// Adds the times as seen in `timezone` to the response.
+    pub fn localized(mut self, timezone: Tz) -> Self {
+        let local = |time: DateTime<Utc>| time.with_timezone(&timezone).fixed_offset();
+        self.local = Some(LocalTimes {
+            timezone,
+            notify_at: local(self.notify_at),
+            start_time: local(self.start_time),
+            end_time: local(self.end_time),
+        });
+        self
+    }
+
+    /// Moves the reminder to `remind` seconds before the start.
+    pub fn with_remind(mut self, remind: i64) -> Self {
+        self.remind = remind;
+        self.notify_at = self.start_time - Duration::seconds(remind);
+        self
+    }
+
+    pub fn notify_at(&self) -> DateTime<Utc> {
+        self.notify_at
     }
 
-    pub fn modulo(&self, date:DateTime<Utc>) -> i64 {
-        self.start_time.signed_duration_since(date).num_seconds() % self.recurrence
+    pub fn category(&self) -> Category {
+        self.category
+    }
+
+    /// Personal and guild events take up the user's time; Skyblock events only happen around them.
+    pub(crate) fn takes_time(&self) -> bool {
+        matches!(self.category, Category::Personal | Category::Guild)
+    }
+
+    /// Appends a line to the description.
+    pub fn with_note(mut self, note: &str) -> Self {
+        if !self.description.is_empty() {
+            self.description.push('\n');
+        }
+        self.description.push_str(note);
+        self
     }
 
     pub(crate) fn is_upcoming(&self, date: DateTime<Utc>) -> bool {
         self.start_time > date
     }
 
-    pub(crate) fn get_start_time(&self) -> DateTime<Utc> {
-        self.start_time
+    pub(crate) fn get_end_time(&self) -> DateTime<Utc> {
+        self.end_time.max(self.start_time)
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    pub(crate) fn is_ongoing(&self, date: DateTime<Utc>) -> bool {
+        self.start_time <= date && date < self.end_time
+    }
+
+    /// Whether the event intersects `[from, to)`. Instant events count when they start inside it.
+    pub(crate) fn overlaps(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> bool {
+        to.is_none_or(|to| self.start_time < to)
+            && from.is_none_or(|from| self.start_time >= from || self.get_end_time() > from)
+    }
+
+    /// The event moved by `offset`, unless that is past the last representable time.
+    fn shifted(&self, offset: Duration) -> Option<Event> {
+        Some(Event {
+            notify_at: self.notify_at.checked_add_signed(offset)?,
+            start_time: self.start_time.checked_add_signed(offset)?,
+            end_time: self.end_time.checked_add_signed(offset)?,
+            ..self.clone()
+        })
+    }
+
+    /// How far the `index`th occurrence starts after the first, if that is representable. With a
+    /// timezone the wall-clock time is kept, so in UTC occurrences move by the DST shift.
+    fn recurrence_offset(&self, index: i64) -> Option<Duration> {
+        let step = self.recurrence.checked_mul(index).and_then(Duration::try_seconds)?;
+        let Some(timezone) = self.timezone else {
+            return Some(step);
+        };
+        let local = self.start_time.with_timezone(&timezone).naive_local().checked_add_signed(step)?;
+        // A time skipped when clocks go forward happens an hour later; a repeated one the first time.
+        let start = timezone.from_local_datetime(&local).earliest()
+            .or_else(|| timezone.from_local_datetime(&(local + Duration::hours(1))).earliest());
+        Some(start.map_or(step, |start| start.with_timezone(&Utc) - self.start_time))
     }
 
-    fn next_occurrence(&self, date:DateTime<Utc>) -> Option<DateTime<Utc>> {
-        if self.recurrence != 0 {
-            let mut occurrence_start = self.start_time;
-            while occurrence_start <= date {
-                occurrence_start = occurrence_start + Duration::seconds(self.recurrence);
+    /// Occurrences of the event overlapping `[from, to)`.
+    ///
+    /// Recurring events are only expanded inside the window, so callers bound it; without an
+    /// end only the first overlapping occurrence is returned.
+    pub(crate) fn occurrences(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Vec<Event> {
+        if self.recurrence <= 0 {
+            return if self.overlaps(from, to) { vec![self.clone()] } else { Vec::new() };
+        }
+
+        let length = self.get_end_time() - self.start_time;
+        // Skip straight to the first occurrence that can still reach `from`, or the one before
+        // with a timezone, as a DST change may have moved that one into the window.
+        let early = if self.timezone.is_some() { 1 } else { 0 };
+        let mut index = from
+            .map(|from| ((from - self.start_time - length).num_seconds().div_euclid(self.recurrence) - early).max(0))
+            .unwrap_or(0);
+
+        let mut occurrences = Vec::new();
+        while let Some(occurrence) = self.recurrence_offset(index).and_then(|offset| self.shifted(offset)) {
+            if to.is_some_and(|to| occurrence.start_time >= to) {
+                break;
+            }
+            if occurrence.overlaps(from, to) {
+                occurrences.push(occurrence);
+                if to.is_none() {
+                    break;
+                }
             }
-            return Some(occurrence_start)
+            index += 1;
         }
-        None
+        occurrences
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::calendar::{Calendar, EventQuery, DEFAULT_WINDOW_DAYS};
+
+    fn recurring(start: DateTime<Utc>, recurrence: i64) -> Event {
+        let duration = 3600;
+        Event::new("Raid".to_string(), String::new(), start, start, start + Duration::seconds(duration), duration, recurrence, 0)
+    }
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn recurring_events_are_expanded_inside_the_window() {
+        let event = recurring(at("2026-01-01T18:00:00Z"), 24 * 3600);
+        let starts: Vec<_> = event.occurrences(Some(at("2026-01-10T18:30:00Z")), Some(at("2026-01-13T18:00:00Z")))
+            .into_iter()
+            .map(|occurrence| occurrence.start_time)
+            .collect();
+        // The one running at `from` counts, the one starting at `to` does not.
+        assert_eq!(starts, [at("2026-01-10T18:00:00Z"), at("2026-01-11T18:00:00Z"), at("2026-01-12T18:00:00Z")]);
+
+        // Without `to` queries list a default window rather than a single occurrence.
+        let query = EventQuery { from: Some(at("2026-01-10T00:00:00Z")), ..EventQuery::default() };
+        assert_eq!(event.occurrences(query.from, query.window_end()).len(), DEFAULT_WINDOW_DAYS as usize);
+
+        assert_eq!(event.occurrences(None, None).len(), 1);
+        assert!(event.occurrences(None, Some(at("2025-12-31T00:00:00Z"))).is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn later_occurrences_can_be_ongoing() {
+        let mut calendar = Calendar::new("Raids".to_string(), None);
+        calendar.add_event(recurring(at("2026-01-01T18:00:00Z"), 24 * 3600));
+        let query = EventQuery { ongoing: true, now: at("2026-01-10T18:30:00Z"), ..EventQuery::default() };
+        let ongoing = calendar.occurrences(&query);
+        assert_eq!(ongoing.len(), 1);
+        assert_eq!(ongoing[0].start_time, at("2026-01-10T18:00:00Z"));
+
+        assert!(calendar.occurrences(&EventQuery { now: at("2026-01-10T20:00:00Z"), ..query.clone() }).is_empty());
+        let elsewhere = EventQuery { from: Some(at("2026-02-01T00:00:00Z")), ..query };
+        assert!(calendar.occurrences(&elsewhere).is_empty());
+    }
+
+    #[test]
+    fn far_occurrences_end_the_expansion_instead_of_overflowing() {
+        let event = recurring(at("2026-01-01T18:00:00Z"), i64::MAX / 2);
+        assert_eq!(event.occurrences(Some(at("2026-01-01T00:00:00Z")), Some(DateTime::<Utc>::MAX_UTC)).len(), 1);
+        assert!(event.occurrences(Some(DateTime::<Utc>::MAX_UTC - Duration::days(1)), Some(DateTime::<Utc>::MAX_UTC)).is_empty());
+        let late = EventQuery { from: Some(DateTime::<Utc>::MAX_UTC - Duration::days(1)), ..EventQuery::default() };
+        assert!(late.validate().is_err(), "the default window would end past the last representable time");
+        let zoned = event.with_timezone(chrono_tz::Europe::Berlin);
+        assert_eq!(zoned.occurrences(Some(at("2026-01-01T00:00:00Z")), Some(DateTime::<Utc>::MAX_UTC)).len(), 1);
+    }
+
+    #[test]
+    fn zoned_events_keep_their_wall_clock_time_across_dst() {
+        let berlin = chrono_tz::Europe::Berlin;
+        // 20:00 in Berlin, a week before clocks go forward on 2026-03-29.
+        let event = recurring(at("2026-03-22T19:00:00Z"), 7 * 24 * 3600).with_timezone(berlin);
+        let starts: Vec<_> = event.occurrences(Some(at("2026-03-22T00:00:00Z")), Some(at("2026-04-06T00:00:00Z")))
+            .into_iter()
+            .map(|occurrence| occurrence.start_time)
+            .collect();
+        assert_eq!(starts, [at("2026-03-22T19:00:00Z"), at("2026-03-29T18:00:00Z"), at("2026-04-05T18:00:00Z")]);
+
+        // Queries starting after the change still find the occurrence it moved earlier.
+        let moved = event.occurrences(Some(at("2026-03-29T18:30:00Z")), Some(at("2026-03-30T00:00:00Z")));
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].end_time - moved[0].start_time, Duration::hours(1));
+
+        // Without a timezone the event recurs in fixed UTC steps.
+        let fixed = recurring(at("2026-03-22T19:00:00Z"), 7 * 24 * 3600);
+        assert_eq!(fixed.occurrences(Some(at("2026-03-29T00:00:00Z")), Some(at("2026-03-30T00:00:00Z")))[0].start_time, at("2026-03-29T19:00:00Z"));
+    }
+
+    #[test]
+    fn skipped_local_times_happen_an_hour_later() {
+        let berlin = chrono_tz::Europe::Berlin;
+        // 02:30 in Berlin does not exist on 2026-03-29.
+        let event = recurring(at("2026-03-28T01:30:00Z"), 24 * 3600).with_timezone(berlin);
+        let starts: Vec<_> = event.occurrences(Some(at("2026-03-28T00:00:00Z")), Some(at("2026-03-31T00:00:00Z")))
+            .into_iter()
+            .map(|occurrence| occurrence.localized(berlin).local.unwrap().start_time.to_rfc3339())
+            .collect();
+        assert_eq!(starts, ["2026-03-28T02:30:00+01:00", "2026-03-29T03:30:00+02:00", "2026-03-30T02:30:00+02:00"]);
+    }
+}
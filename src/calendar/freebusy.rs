@@ -0,0 +1,164 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::calendar::event::Event;
+use crate::calendar::skyblock::{generate_calendar, RULES};
+
+/// Tag of the events that only happen because of the mayor's perks.
+const MAYOR_TAG: &str = "mayor";
+
+/// The time from `start` until `end`.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub struct Interval {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Interval {
+    /// The part of `event` inside `[from, to)`; events without a length take up no time.
+    fn of(event: &Event, from: DateTime<Utc>, to: DateTime<Utc>) -> Option<Interval> {
+        let interval = Interval { start: event.start_time.max(from), end: event.get_end_time().min(to) };
+        (interval.start < interval.end).then_some(interval)
+    }
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct FreeBusy {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// When any of the users is busy, merged and sorted.
+    pub busy: Vec<Interval>,
+    /// The gaps between them that are long enough.
+    pub free: Vec<Interval>,
+}
+
+impl FreeBusy {
+    /// Free/busy in `[from, to)` of the merged `busy` intervals, listing free slots of at least `min_free`.
+    pub fn new(busy: Vec<Interval>, from: DateTime<Utc>, to: DateTime<Utc>, min_free: Duration) -> FreeBusy {
+        let free = free(&busy, from, to, min_free);
+        FreeBusy { from, to, busy, free }
+    }
+}
+
+/// Merges overlapping and adjacent intervals, sorted by start.
+pub fn merge(mut intervals: Vec<Interval>) -> Vec<Interval> {
+    intervals.sort_by_key(|interval| interval.start);
+    let mut merged: Vec<Interval> = Vec::with_capacity(intervals.len());
+    for interval in intervals {
+        match merged.last_mut() {
+            Some(last) if interval.start <= last.end => last.end = last.end.max(interval.end),
+            _ => merged.push(interval),
+        }
+    }
+    merged
+}
+
+/// The merged times in `[from, to)` taken up by personal and guild events among `occurrences`.
+pub fn busy<'a>(occurrences: impl IntoIterator<Item = &'a Event>, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Interval> {
+    merge(occurrences.into_iter()
+        .filter(|event| event.takes_time())
+        .filter_map(|event| Interval::of(event, from, to))
+        .collect())
+}
+
+/// Gaps of at least `min` in `[from, to)` around the merged `busy` intervals.
+pub fn free(busy: &[Interval], from: DateTime<Utc>, to: DateTime<Utc>, min: Duration) -> Vec<Interval> {
+    let mut free = Vec::new();
+    let mut start = from;
+    for interval in busy.iter().chain([&Interval { start: to, end: to }]) {
+        if interval.start > start && interval.start - start >= min {
+            free.push(Interval { start, end: interval.start });
+        }
+        start = start.max(interval.end);
+    }
+    free
+}
+
+/// Keys of the Skyblock events a slot can be kept clear of, e.g. `dark_auction`.
+pub fn avoidable() -> Vec<&'static str> {
+    RULES.iter().map(|rule| rule.key).chain([MAYOR_TAG]).collect()
+}
+
+/// When the Skyblock events with one of the `keys` run in `[from, to)`, merged. `mayor` stands
+/// for all mayor perk events.
+pub fn skyblock_busy(keys: &[String], from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Interval> {
+    if keys.is_empty() {
+        return Vec::new();
+    }
+    merge(generate_calendar(from, to).list_events().into_iter()
+        .filter(|event| keys.iter().any(|key| event.has_tag(key)))
+        .filter_map(|event| Interval::of(event, from, to))
+        .collect())
+}
+
+/// The first stretch of `length` in `[from, to)` overlapping none of the merged `busy` intervals.
+pub fn first_free(busy: &[Interval], from: DateTime<Utc>, to: DateTime<Utc>, length: Duration) -> Option<Interval> {
+    free(busy, from, to, length).first().map(|gap| Interval { start: gap.start, end: gap.start + length })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::event::Category;
+    use crate::calendar::skyblock::SkyblockDateTime;
+
+    fn base() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_800_000_000, 0).unwrap()
+    }
+
+    /// `[start, end)` in minutes after [`base`].
+    fn minutes(start: i64, end: i64) -> Interval {
+        Interval { start: base() + Duration::minutes(start), end: base() + Duration::minutes(end) }
+    }
+
+    fn event(start: i64, end: i64, category: Category) -> Event {
+        let Interval { start, end } = minutes(start, end);
+        Event::new("Meeting".to_string(), String::new(), start, start, end, (end - start).num_seconds(), 0, 0)
+            .with_category(category)
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_intervals_merge() {
+        let merged = merge(vec![minutes(50, 60), minutes(0, 10), minutes(10, 20), minutes(15, 18), minutes(30, 40), minutes(35, 45)]);
+        assert_eq!(merged, [minutes(0, 20), minutes(30, 45), minutes(50, 60)]);
+        assert!(merge(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn only_personal_and_guild_events_are_busy() {
+        let events = [
+            event(0, 30, Category::Personal),
+            event(20, 40, Category::Guild),
+            event(60, 90, Category::Skyblock),
+            event(100, 100, Category::Personal),
+            event(110, 200, Category::Personal),
+        ];
+        let (from, to) = (base(), base() + Duration::minutes(120));
+        assert_eq!(busy(&events, from, to), [minutes(0, 40), minutes(110, 120)]);
+    }
+
+    #[test]
+    fn free_slots_are_the_long_enough_gaps() {
+        let busy = [minutes(10, 20), minutes(25, 40), minutes(90, 120)];
+        let (from, to) = (base(), base() + Duration::minutes(120));
+        assert_eq!(free(&busy, from, to, Duration::minutes(10)), [minutes(0, 10), minutes(40, 90)]);
+        assert_eq!(free(&busy, from, to, Duration::minutes(5)), [minutes(0, 10), minutes(20, 25), minutes(40, 90)]);
+        assert_eq!(free(&[], from, to, Duration::minutes(5)), [minutes(0, 120)]);
+        assert!(free(&[minutes(0, 120)], from, to, Duration::minutes(1)).is_empty());
+
+        assert_eq!(first_free(&busy, from, to, Duration::minutes(30)), Some(minutes(40, 70)));
+        assert_eq!(first_free(&busy, from, to, Duration::minutes(60)), None);
+    }
+
+    #[test]
+    fn skyblock_events_can_be_kept_clear_of() {
+        // Dark Auctions take place every third Skyblock day, from the first of year 402, and last the whole day.
+        let from = SkyblockDateTime::new(1, 1, 402).as_datetime();
+        let to = SkyblockDateTime::new(7, 1, 402).as_datetime();
+        let day = |day: i8| SkyblockDateTime::new(day, 1, 402).as_datetime();
+        let auctions = skyblock_busy(&["dark_auction".to_string()], from, to);
+        assert_eq!(auctions, [Interval { start: day(1), end: day(2) }, Interval { start: day(4), end: day(5) }]);
+        assert!(skyblock_busy(&[], from, to).is_empty());
+        assert!(avoidable().contains(&"dark_auction") && avoidable().contains(&MAYOR_TAG));
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{LazyLock, RwLock};
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::calendar::mayor::Perk;
+use crate::calendar::skyblock::{self, SECONDS_PER_DAY};
+use crate::config::data_file;
+use crate::helpers::{read_json_from_file, write_json_to_file};
+
+const CONTESTS_FILE: &str = "jacob_contests.json";
+/// Every contest is held on this many different crops.
+pub const CROPS_PER_CONTEST: usize = 3;
+
+pub static JACOB_CONTESTS: LazyLock<RwLock<ContestSchedule>> = LazyLock::new(|| {
+    let schedule = read_json_from_file(data_file(CONTESTS_FILE)).unwrap_or_else(|e| {
+        warn!("No Jacob's contest crops loaded, could not read {}: {}", CONTESTS_FILE, e);
+        ContestSchedule::default()
+    });
+    RwLock::new(schedule)
+});
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, ToSchema)]
+pub enum Crop {
+    #[serde(alias = "CACTUS")]
+    Cactus,
+    #[serde(alias = "CARROT_ITEM")]
+    Carrot,
+    #[serde(rename = "Cocoa Beans", alias = "INK_SACK:3")]
+    CocoaBeans,
+    #[serde(alias = "MELON")]
+    Melon,
+    #[serde(alias = "MUSHROOM_COLLECTION")]
+    Mushroom,
+    #[serde(rename = "Nether Wart", alias = "NETHER_STALK")]
+    NetherWart,
+    #[serde(alias = "POTATO_ITEM")]
+    Potato,
+    #[serde(alias = "PUMPKIN")]
+    Pumpkin,
+    #[serde(rename = "Sugar Cane", alias = "SUGAR_CANE")]
+    SugarCane,
+    #[serde(alias = "WHEAT")]
+    Wheat,
+}
+
+impl Crop {
+    pub const ALL: [Crop; 10] = [
+        Crop::Cactus, Crop::Carrot, Crop::CocoaBeans, Crop::Melon, Crop::Mushroom,
+        Crop::NetherWart, Crop::Potato, Crop::Pumpkin, Crop::SugarCane, Crop::Wheat,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Crop::Cactus => "Cactus",
+            Crop::Carrot => "Carrot",
+            Crop::CocoaBeans => "Cocoa Beans",
+            Crop::Melon => "Melon",
+            Crop::Mushroom => "Mushroom",
+            Crop::NetherWart => "Nether Wart",
+            Crop::Potato => "Potato",
+            Crop::Pumpkin => "Pumpkin",
+            Crop::SugarCane => "Sugar Cane",
+            Crop::Wheat => "Wheat",
+        }
+    }
+}
+
+impl fmt::Display for Crop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    /// Accepts display names in any case, with spaces or underscores ("nether_wart").
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wanted = s.trim().replace('_', " ").to_lowercase();
+        Crop::ALL.into_iter()
+            .find(|crop| crop.name().to_lowercase() == wanted)
+            .ok_or_else(|| format!("Unknown crop '{}'", s))
+    }
+}
+
+/// Known contest crops keyed by contest start (unix seconds).
+///
+/// Serialized in the community format used by the Jacob's contest trackers:
+/// `{ "contests": { "1729461300": ["Cactus", "Wheat", "Potato"], ... } }`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct ContestSchedule {
+    pub contests: BTreeMap<i64, Vec<Crop>>,
+}
+
+impl ContestSchedule {
+    pub fn crops_at(&self, start: DateTime<Utc>) -> Option<&Vec<Crop>> {
+        self.contests.get(&start.timestamp())
+    }
+
+    /// Merges `other` into this schedule, returning how many contests were added or replaced.
+    /// Nothing is merged when any entry is not a contest start with three different crops.
+    pub fn import(&mut self, other: ContestSchedule) -> Result<usize, String> {
+        let problems: Vec<String> = other.contests.iter()
+            .filter_map(|(&start, crops)| check_contest(start, crops).err())
+            .collect();
+        match problems.as_slice() {
+            [] => {}
+            [problem] => return Err(problem.clone()),
+            [problem, rest @ ..] => return Err(format!("{} (and {} more invalid contests)", problem, rest.len())),
+        }
+        let imported = other.contests.len();
+        self.contests.extend(other.contests);
+        Ok(imported)
+    }
+
+    pub fn save(&self) -> Result<(), serde_json::Error> {
+        write_json_to_file(data_file(CONTESTS_FILE), self)?;
+        info!("Saved {} Jacob's contests to {}", self.contests.len(), CONTESTS_FILE);
+        Ok(())
+    }
+
+    /// Upcoming contests after `from`, optionally only those featuring `crop`.
+    pub fn upcoming(&self, from: DateTime<Utc>, crop: Option<Crop>, limit: usize) -> Vec<Contest> {
+        self.contests
+            .range(from.timestamp()..)
+            .filter(|(_, crops)| crop.is_none_or(|crop| crops.contains(&crop)))
+            .filter_map(|(&start, crops)| {
+                // Files written before imports were checked may hold keys without a Skyblock date.
+                let start = DateTime::from_timestamp(start, 0).filter(|start| skyblock::dated_times().contains(start))?;
+                Some(Contest::new(start, crops.clone()))
+            })
+            .take(limit)
+            .collect()
+    }
+}
+
+fn check_contest(start: i64, crops: &[Crop]) -> Result<(), String> {
+    if !DateTime::from_timestamp(start, 0).is_some_and(skyblock::contest_starts_at) {
+        return Err(format!("No Jacob's contest starts at {}", start));
+    }
+    let mut distinct = crops.to_vec();
+    distinct.sort();
+    distinct.dedup();
+    if crops.len() != CROPS_PER_CONTEST || distinct.len() != CROPS_PER_CONTEST {
+        return Err(format!("The contest at {} must have {} different crops", start, CROPS_PER_CONTEST));
+    }
+    Ok(())
+}
+
+/// Share of participants, from the top, that reach each medal bracket.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+pub struct Brackets {
+    pub diamond: f64,
+    pub platinum: f64,
+    pub gold: f64,
+    pub silver: f64,
+    pub bronze: f64,
+}
+
+impl Brackets {
+    pub const DEFAULT: Brackets = Brackets { diamond: 0.02, platinum: 0.05, gold: 0.10, silver: 0.30, bronze: 0.60 };
+
+    /// Brackets for a contest held under the given mayor perks.
+    pub fn with_perks(perks: &[Perk]) -> Self {
+        if perks.contains(&Perk::Goated) {
+            let goated = |share: f64| share * 1.1;
+            let b = Self::DEFAULT;
+            return Brackets {
+                diamond: goated(b.diamond),
+                platinum: goated(b.platinum),
+                gold: goated(b.gold),
+                silver: goated(b.silver),
+                bronze: goated(b.bronze),
+            };
+        }
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct Contest {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub crops: Vec<Crop>,
+    pub brackets: Brackets,
+}
+
+impl Contest {
+    pub fn new(start_time: DateTime<Utc>, crops: Vec<Crop>) -> Self {
+        Contest {
+            start_time,
+            end_time: start_time + Duration::seconds(SECONDS_PER_DAY),
+            crops,
+            brackets: Brackets::with_perks(&skyblock::mayor_perks_at(start_time)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::skyblock::SkyblockDateTime;
+
+    /// Start of the contest on the given day; contests take place every third day from the 2nd.
+    fn contest_start(day: i8, month: i8, year: i16) -> i64 {
+        SkyblockDateTime::new(day, month, year).as_datetime().timestamp()
+    }
+
+    fn schedule(contests: &[(i64, Vec<Crop>)]) -> ContestSchedule {
+        ContestSchedule { contests: contests.iter().cloned().collect() }
+    }
+
+    #[test]
+    fn import_merges_valid_contests() {
+        let mut contests = schedule(&[(contest_start(2, 1, 400), vec![Crop::Wheat, Crop::Carrot, Crop::Potato])]);
+        let imported = contests.import(schedule(&[
+            (contest_start(2, 1, 400), vec![Crop::Cactus, Crop::Melon, Crop::Pumpkin]),
+            (contest_start(5, 1, 400), vec![Crop::Wheat, Crop::Mushroom, Crop::SugarCane]),
+        ]));
+        assert_eq!(imported, Ok(2));
+        assert_eq!(contests.contests.len(), 2);
+        assert_eq!(contests.contests[&contest_start(2, 1, 400)][0], Crop::Cactus);
+    }
+
+    #[test]
+    fn import_rejects_invalid_contests() {
+        let crops = vec![Crop::Wheat, Crop::Carrot, Crop::Potato];
+        let mut contests = ContestSchedule::default();
+        for invalid in [
+            schedule(&[(contest_start(3, 1, 400), crops.clone())]),
+            schedule(&[(contest_start(2, 1, 400) + 1, crops.clone())]),
+            schedule(&[(i64::MAX, crops.clone())]),
+            schedule(&[(0, crops.clone())]),
+            schedule(&[(contest_start(2, 1, 400), vec![Crop::Wheat, Crop::Carrot])]),
+            schedule(&[(contest_start(2, 1, 400), vec![Crop::Wheat, Crop::Wheat, Crop::Potato])]),
+            schedule(&[
+                (contest_start(5, 1, 400), crops.clone()),
+                (contest_start(3, 1, 400), crops.clone()),
+            ]),
+        ] {
+            assert!(contests.import(invalid.clone()).is_err(), "imported {:?}", invalid);
+        }
+        assert!(contests.contests.is_empty());
+    }
+
+    #[test]
+    fn upcoming_contests_are_filtered_by_crop() {
+        let contests = schedule(&[
+            (contest_start(2, 1, 400), vec![Crop::Wheat, Crop::Carrot, Crop::Potato]),
+            (contest_start(5, 1, 400), vec![Crop::Cactus, Crop::Melon, Crop::Pumpkin]),
+            (contest_start(8, 1, 400), vec![Crop::Wheat, Crop::Mushroom, Crop::SugarCane]),
+            (i64::MAX, vec![Crop::Wheat, Crop::Melon, Crop::Pumpkin]),
+        ]);
+        let from = DateTime::from_timestamp(contest_start(3, 1, 400), 0).unwrap();
+
+        let wheat = contests.upcoming(from, Some(Crop::Wheat), 10);
+        assert_eq!(wheat.len(), 1);
+        assert_eq!(wheat[0].start_time.timestamp(), contest_start(8, 1, 400));
+        assert_eq!(wheat[0].end_time - wheat[0].start_time, Duration::seconds(SECONDS_PER_DAY));
+        assert_eq!(contests.upcoming(from, None, 1).len(), 1);
+    }
+
+    #[test]
+    fn crops_parse_from_names_and_tracker_keys() {
+        assert_eq!("nether_wart".parse(), Ok(Crop::NetherWart));
+        assert_eq!("Cocoa Beans".parse(), Ok(Crop::CocoaBeans));
+        assert!("Carrots".parse::<Crop>().is_err());
+        assert_eq!(serde_json::from_str::<Crop>("\"INK_SACK:3\"").unwrap(), Crop::CocoaBeans);
+    }
+}
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use crate::calendar::skyblock::{SkyblockDateTime, DAYS_PER_YEAR, SECONDS_PER_DAY};
+use crate::config::data_file;
+use crate::helpers::read_json_from_file;
+
+const OVERRIDES_FILE: &str = "skyblock_mayor_events.json";
+
+/// Perk effects read from [`OVERRIDES_FILE`], replacing the built-in effects of each listed perk.
+///
+/// The file maps perk names to effect lists, for example:
+/// `{ "Fishing Festival": [{ "type": "extra_event", "title": "Fishing Festival", ... }] }`.
+/// Effects that cannot be applied are skipped with a warning.
+static OVERRIDES: LazyLock<HashMap<Perk, Vec<PerkEffect>>> = LazyLock::new(|| {
+    let overrides = read_json_from_file(data_file(OVERRIDES_FILE)).unwrap_or_else(|e| {
+        warn!("Using built-in perk effects only, could not read {}: {}", OVERRIDES_FILE, e);
+        HashMap::new()
+    });
+    valid_overrides(overrides)
+});
+
+/// Drops the effects that would break calendar generation, e.g. an interval of 0 days.
+fn valid_overrides(overrides: HashMap<Perk, Vec<PerkEffect>>) -> HashMap<Perk, Vec<PerkEffect>> {
+    overrides.into_iter()
+        .map(|(perk, effects)| {
+            let effects = effects.into_iter()
+                .filter(|effect| match effect.check() {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!("Skipping an effect of {} in {}: {}", perk, OVERRIDES_FILE, e);
+                        false
+                    }
+                })
+                .collect();
+            (perk, effects)
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub enum Mayor {
+    Aatrox,
+    Cole,
+    Diana,
+    Diaz,
+    Finnegan,
+    Foxy,
+    Marina,
+    Paul,
+    Derpy,
+    Jerry,
+    Scorpius,
+}
+
+impl Mayor {
+    pub const ALL: [Mayor; 11] = [
+        Mayor::Aatrox, Mayor::Cole, Mayor::Diana, Mayor::Diaz, Mayor::Finnegan, Mayor::Foxy,
+        Mayor::Marina, Mayor::Paul, Mayor::Derpy, Mayor::Jerry, Mayor::Scorpius,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mayor::Aatrox => "Aatrox",
+            Mayor::Cole => "Cole",
+            Mayor::Diana => "Diana",
+            Mayor::Diaz => "Diaz",
+            Mayor::Finnegan => "Finnegan",
+            Mayor::Foxy => "Foxy",
+            Mayor::Marina => "Marina",
+            Mayor::Paul => "Paul",
+            Mayor::Derpy => "Derpy",
+            Mayor::Jerry => "Jerry",
+            Mayor::Scorpius => "Scorpius",
+        }
+    }
+
+    /// Colour used when displaying events caused by the mayor's perks.
+    pub fn color(&self) -> &'static str {
+        match self {
+            Mayor::Aatrox => "#AA0000",
+            Mayor::Cole => "#FFAA00",
+            Mayor::Diana => "#00AA00",
+            Mayor::Diaz => "#FFD700",
+            Mayor::Finnegan => "#55FF55",
+            Mayor::Foxy => "#FF55FF",
+            Mayor::Marina => "#55FFFF",
+            Mayor::Paul => "#FF5555",
+            Mayor::Derpy => "#AA00AA",
+            Mayor::Jerry => "#5555FF",
+            Mayor::Scorpius => "#AA00AA",
+        }
+    }
+
+    /// Icon key of the mayor, e.g. `mayor_diana`.
+    pub fn icon(&self) -> String {
+        format!("mayor_{}", self.name().to_lowercase())
+    }
+}
+
+impl fmt::Display for Mayor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Mayor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Mayor::ALL.into_iter()
+            .find(|mayor| mayor.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("Unknown mayor '{}'", s))
+    }
+}
+
+impl TryFrom<String> for Mayor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Mayor> for String {
+    fn from(mayor: Mayor) -> Self {
+        mayor.name().to_string()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub enum Perk {
+    // Aatrox
+    SlashedPricing,
+    SlayerXpBuff,
+    Pathfinder,
+    // Cole
+    MiningFiesta,
+    MiningXpBuff,
+    MoltenForge,
+    Prospection,
+    // Diana
+    Lucky,
+    MythologicalRitual,
+    PetXpBuff,
+    SharingIsCaring,
+    // Diaz
+    LongTermInvestment,
+    ShoppingSpree,
+    StockExchange,
+    VolumeTrading,
+    // Finnegan
+    BloomingBusiness,
+    FarmingSimulator,
+    Goated,
+    PeltPocalypse,
+    PestEradicator,
+    // Foxy
+    ATimeForGiving,
+    ChivalrousCarnival,
+    ExtraEventFishing,
+    ExtraEventMining,
+    ExtraEventSpooky,
+    SweetBenevolence,
+    // Marina
+    DoubleTrouble,
+    FishingFestival,
+    FishingXpBuff,
+    LuckOfTheSea,
+    // Paul
+    Benediction,
+    Ezpz,
+    Marauder,
+    // Derpy
+    DoubleMobsHp,
+    MoarSkillz,
+    QuadTaxes,
+    TurboMinions,
+    // Jerry
+    Jerrypocalypse,
+    Perkpocalypse,
+    Statspocalypse,
+    // Scorpius
+    Bribe,
+    DarkerAuctions,
+}
+
+impl Perk {
+    pub const ALL: [Perk; 42] = [
+        Perk::SlashedPricing, Perk::SlayerXpBuff, Perk::Pathfinder,
+        Perk::MiningFiesta, Perk::MiningXpBuff, Perk::MoltenForge, Perk::Prospection,
+        Perk::Lucky, Perk::MythologicalRitual, Perk::PetXpBuff, Perk::SharingIsCaring,
+        Perk::LongTermInvestment, Perk::ShoppingSpree, Perk::StockExchange, Perk::VolumeTrading,
+        Perk::BloomingBusiness, Perk::FarmingSimulator, Perk::Goated, Perk::PeltPocalypse, Perk::PestEradicator,
+        Perk::ATimeForGiving, Perk::ChivalrousCarnival, Perk::ExtraEventFishing, Perk::ExtraEventMining,
+        Perk::ExtraEventSpooky, Perk::SweetBenevolence,
+        Perk::DoubleTrouble, Perk::FishingFestival, Perk::FishingXpBuff, Perk::LuckOfTheSea,
+        Perk::Benediction, Perk::Ezpz, Perk::Marauder,
+        Perk::DoubleMobsHp, Perk::MoarSkillz, Perk::QuadTaxes, Perk::TurboMinions,
+        Perk::Jerrypocalypse, Perk::Perkpocalypse, Perk::Statspocalypse,
+        Perk::Bribe, Perk::DarkerAuctions,
+    ];
+
+    /// Name as shown in game and in the Hypixel election resource.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Perk::SlashedPricing => "SLASHED Pricing",
+            Perk::SlayerXpBuff => "Slayer XP Buff",
+            Perk::Pathfinder => "Pathfinder",
+            Perk::MiningFiesta => "Mining Fiesta",
+            Perk::MiningXpBuff => "Mining XP Buff",
+            Perk::MoltenForge => "Molten Forge",
+            Perk::Prospection => "Prospection",
+            Perk::Lucky => "Lucky!",
+            Perk::MythologicalRitual => "Mythological Ritual",
+            Perk::PetXpBuff => "Pet XP Buff",
+            Perk::SharingIsCaring => "Sharing is Caring",
+            Perk::LongTermInvestment => "Long Term Investment",
+            Perk::ShoppingSpree => "Shopping Spree",
+            Perk::StockExchange => "Stock Exchange",
+            Perk::VolumeTrading => "Volume Trading",
+            Perk::BloomingBusiness => "Blooming Business",
+            Perk::FarmingSimulator => "Farming Simulator",
+            Perk::Goated => "GOATed",
+            Perk::PeltPocalypse => "Pelt-pocalypse",
+            Perk::PestEradicator => "Pest Eradicator",
+            Perk::ATimeForGiving => "A Time for Giving",
+            Perk::ChivalrousCarnival => "Chivalrous Carnival",
+            Perk::ExtraEventFishing => "Extra Event (Fishing)",
+            Perk::ExtraEventMining => "Extra Event (Mining)",
+            Perk::ExtraEventSpooky => "Extra Event (Spooky)",
+            Perk::SweetBenevolence => "Sweet Benevolence",
+            Perk::DoubleTrouble => "Double Trouble",
+            Perk::FishingFestival => "Fishing Festival",
+            Perk::FishingXpBuff => "Fishing XP Buff",
+            Perk::LuckOfTheSea => "Luck of the Sea 2.0",
+            Perk::Benediction => "Benediction",
+            Perk::Ezpz => "EZPZ",
+            Perk::Marauder => "Marauder",
+            Perk::DoubleMobsHp => "DOUBLE MOBS HP!!!",
+            Perk::MoarSkillz => "MOAR SKILLZ!!!",
+            Perk::QuadTaxes => "QUAD TAXES!!!",
+            Perk::TurboMinions => "TURBO MINIONS!!!",
+            Perk::Jerrypocalypse => "Jerrypocalypse",
+            Perk::Perkpocalypse => "Perkpocalypse",
+            Perk::Statspocalypse => "Statspocalypse",
+            Perk::Bribe => "Bribe",
+            Perk::DarkerAuctions => "Darker Auctions",
+        }
+    }
+
+    pub fn mayor(&self) -> Mayor {
+        match self {
+            Perk::SlashedPricing | Perk::SlayerXpBuff | Perk::Pathfinder => Mayor::Aatrox,
+            Perk::MiningFiesta | Perk::MiningXpBuff | Perk::MoltenForge | Perk::Prospection => Mayor::Cole,
+            Perk::Lucky | Perk::MythologicalRitual | Perk::PetXpBuff | Perk::SharingIsCaring => Mayor::Diana,
+            Perk::LongTermInvestment | Perk::ShoppingSpree | Perk::StockExchange | Perk::VolumeTrading => Mayor::Diaz,
+            Perk::BloomingBusiness | Perk::FarmingSimulator | Perk::Goated | Perk::PeltPocalypse
+            | Perk::PestEradicator => Mayor::Finnegan,
+            Perk::ATimeForGiving | Perk::ChivalrousCarnival | Perk::ExtraEventFishing | Perk::ExtraEventMining
+            | Perk::ExtraEventSpooky | Perk::SweetBenevolence => Mayor::Foxy,
+            Perk::DoubleTrouble | Perk::FishingFestival | Perk::FishingXpBuff | Perk::LuckOfTheSea => Mayor::Marina,
+            Perk::Benediction | Perk::Ezpz | Perk::Marauder => Mayor::Paul,
+            Perk::DoubleMobsHp | Perk::MoarSkillz | Perk::QuadTaxes | Perk::TurboMinions => Mayor::Derpy,
+            Perk::Jerrypocalypse | Perk::Perkpocalypse | Perk::Statspocalypse => Mayor::Jerry,
+            Perk::Bribe | Perk::DarkerAuctions => Mayor::Scorpius,
+        }
+    }
+
+    /// Resolves a perk from the Hypixel election resource.
+    ///
+    /// Foxy's "Extra Event" perks share a name and only differ in their description.
+    pub fn from_api(name: &str, description: &str) -> Option<Perk> {
+        if name.eq_ignore_ascii_case("Extra Event") {
+            return if description.contains("Mining Fiesta") {
+                Some(Perk::ExtraEventMining)
+            } else if description.contains("Fishing Festival") {
+                Some(Perk::ExtraEventFishing)
+            } else if description.contains("Spooky Festival") {
+                Some(Perk::ExtraEventSpooky)
+            } else {
+                None
+            };
+        }
+        name.parse().ok()
+    }
+
+    /// Calendar effects of this perk, taking [`OVERRIDES_FILE`] into account.
+    pub fn effects(&self) -> Vec<PerkEffect> {
+        if let Some(effects) = OVERRIDES.get(self) {
+            return effects.clone();
+        }
+        self.builtin_effects()
+    }
+
+    fn builtin_effects(&self) -> Vec<PerkEffect> {
+        let extra_event = |title: &str, description: &str, schedule: TermSchedule, duration: i64| {
+            PerkEffect::ExtraEvent {
+                title: title.to_string(),
+                description: description.to_string(),
+                schedule,
+                duration,
+            }
+        };
+        let fishing_festival = "Sharks spawn while fishing on the first 3 days of the month.";
+        let mining_fiesta = "Double drops and Refined Minerals while mining.";
+        let spooky_festival = "Trick or Treat! Spooky mobs and candy appear everywhere.";
+
+        match self {
+            Perk::FishingFestival => vec![
+                extra_event("Fishing Festival", fishing_festival, TermSchedule::Monthly { day: 1 }, 3 * SECONDS_PER_DAY),
+            ],
+            Perk::MiningFiesta => vec![
+                extra_event(
+                    "Mining Fiesta",
+                    mining_fiesta,
+                    TermSchedule::Dates { dates: vec![(4, 1), (6, 1), (8, 1), (10, 1), (12, 1)] },
+                    7 * SECONDS_PER_DAY,
+                ),
+            ],
+            Perk::MythologicalRitual => vec![
+                extra_event("Mythological Ritual", "Griffin burrows can be dug up with Diana's ancestral spade.", TermSchedule::WholeTerm, DAYS_PER_YEAR * SECONDS_PER_DAY),
+            ],
+            Perk::ChivalrousCarnival => vec![
+                extra_event("Chivalrous Carnival", "The Carnival is open in the Hub.", TermSchedule::WholeTerm, DAYS_PER_YEAR * SECONDS_PER_DAY),
+            ],
+            Perk::ExtraEventFishing => vec![
+                extra_event("Fishing Festival", fishing_festival, TermSchedule::Dates { dates: vec![(7, 1)] }, 3 * SECONDS_PER_DAY),
+            ],
+            Perk::ExtraEventMining => vec![
+                extra_event("Mining Fiesta", mining_fiesta, TermSchedule::Dates { dates: vec![(7, 1)] }, 7 * SECONDS_PER_DAY),
+            ],
+            Perk::ExtraEventSpooky => vec![
+                extra_event("Spooky Festival", spooky_festival, TermSchedule::Dates { dates: vec![(5, 29)] }, 3 * SECONDS_PER_DAY),
+            ],
+            Perk::DarkerAuctions => vec![
+                PerkEffect::Duration { rule: "dark_auction".to_string(), duration: 2 * SECONDS_PER_DAY },
+            ],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Perk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Perk {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Perk::ALL.into_iter()
+            .find(|perk| perk.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("Unknown perk '{}'", s))
+    }
+}
+
+impl TryFrom<String> for Perk {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Perk> for String {
+    fn from(perk: Perk) -> Self {
+        perk.name().to_string()
+    }
+}
+
+/// Days of a mayor's term an extra event starts on.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TermSchedule {
+    /// The given day of every month.
+    Monthly { day: i8 },
+    /// The listed `(month, day)` pairs.
+    Dates { dates: Vec<(i8, i8)> },
+    /// Once, when the term starts.
+    WholeTerm,
+}
+
+impl TermSchedule {
+    pub fn matches(&self, day: &SkyblockDateTime, term_start: &SkyblockDateTime) -> bool {
+        match self {
+            TermSchedule::Monthly { day: month_day } => day.day() == *month_day,
+            TermSchedule::Dates { dates } => dates.contains(&(day.month(), day.day())),
+            TermSchedule::WholeTerm => day.day_index() == term_start.day_index(),
+        }
+    }
+}
+
+/// How a perk changes the Skyblock calendar while its mayor is in office.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PerkEffect {
+    /// Schedules an event that only exists under this perk.
+    ExtraEvent { title: String, description: String, schedule: TermSchedule, duration: i64 },
+    /// Makes a built-in rule recur every `every_days` Skyblock days.
+    Interval { rule: String, every_days: i64 },
+    /// Makes a built-in rule last `duration` real seconds.
+    Duration { rule: String, duration: i64 },
+}
+
+/// Longest an event may last; the calendar only looks back this far for ongoing events.
+const MAX_DURATION: i64 = DAYS_PER_YEAR * SECONDS_PER_DAY;
+
+impl PerkEffect {
+    /// Whether the effect can be applied to the calendar.
+    fn check(&self) -> Result<(), String> {
+        match self {
+            PerkEffect::Interval { every_days, .. } if *every_days < 1 => {
+                Err(format!("every_days must be at least 1, not {}", every_days))
+            }
+            PerkEffect::ExtraEvent { duration, .. } | PerkEffect::Duration { duration, .. }
+                if !(1..=MAX_DURATION).contains(duration) =>
+            {
+                Err(format!("duration must be between 1 and {} seconds, not {}", MAX_DURATION, duration))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_that_break_generation_are_skipped() {
+        let overrides: HashMap<Perk, Vec<PerkEffect>> = serde_json::from_str(r#"{
+            "Darker Auctions": [
+                { "type": "interval", "rule": "dark_auction", "every_days": 0 },
+                { "type": "duration", "rule": "dark_auction", "duration": -5 },
+                { "type": "interval", "rule": "dark_auction", "every_days": 2 }
+            ],
+            "Fishing Festival": [
+                { "type": "extra_event", "title": "Fishing Festival", "description": "",
+                  "schedule": { "type": "whole_term" }, "duration": 0 }
+            ]
+        }"#).unwrap();
+
+        let overrides = valid_overrides(overrides);
+        assert_eq!(overrides[&Perk::DarkerAuctions], vec![
+            PerkEffect::Interval { rule: "dark_auction".to_string(), every_days: 2 },
+        ]);
+        assert!(overrides[&Perk::FishingFestival].is_empty());
+    }
+
+    #[test]
+    fn built_in_effects_are_valid() {
+        for perk in Perk::ALL {
+            for effect in perk.builtin_effects() {
+                assert_eq!(effect.check(), Ok(()), "{} has an invalid effect", perk);
+            }
+        }
+    }
+
+    #[test]
+    fn perks_resolve_from_the_election_resource() {
+        assert_eq!(Perk::from_api("Extra Event", "Schedules an extra Mining Fiesta event"), Some(Perk::ExtraEventMining));
+        assert_eq!(Perk::from_api("extra event", "Nothing known"), None);
+        assert_eq!(Perk::from_api("lucky!", ""), Some(Perk::Lucky));
+        assert_eq!(Perk::DarkerAuctions.mayor(), Mayor::Scorpius);
+    }
+
+    #[test]
+    fn term_schedules_match_days_of_the_term() {
+        let term_start = SkyblockDateTime::new(27, 3, 403);
+        let monthly = TermSchedule::Monthly { day: 1 };
+        assert!(monthly.matches(&SkyblockDateTime::new(1, 7, 403), &term_start));
+        assert!(!monthly.matches(&SkyblockDateTime::new(2, 7, 403), &term_start));
+        assert!(TermSchedule::Dates { dates: vec![(7, 1)] }.matches(&SkyblockDateTime::new(1, 7, 404), &term_start));
+        assert!(TermSchedule::WholeTerm.matches(&term_start, &term_start));
+        assert!(!TermSchedule::WholeTerm.matches(&term_start.add_days(1), &term_start));
+    }
+}
@@ -1,4 +1,10 @@
+#[allow(clippy::module_inception)]
 pub mod calendar;
 pub mod skyblock;
+pub mod agenda;
+pub mod freebusy;
 pub mod database;
-mod event;
\ No newline at end of file
+pub mod election;
+pub mod jacob;
+pub mod mayor;
+pub(crate) mod event;
\ No newline at end of file
@@ -1,173 +1,500 @@
-use chrono::{DateTime, Duration, Timelike, Utc};
+use std::ops::Range;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use uuid::{Builder, Uuid};
 use crate::calendar::calendar::Calendar;
-use crate::calendar::event::Event;
-use crate::helpers::read_json_from_file;
+use crate::calendar::event::{Category, Event};
+use crate::calendar::election::ELECTION_HISTORY;
+use crate::calendar::jacob::JACOB_CONTESTS;
+use crate::calendar::mayor::Perk;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SkyblockDay {
-    day: i8,
-    month: i8,
+const YEAR_START_TIMESTAMP: i64 = 1560275700;
+pub const JACOBS_CONTEST: &str = "jacobs_contest";
+pub const DAYS_PER_MONTH: i64 = 31;
+pub const MONTHS_PER_YEAR: i64 = 12;
+pub const DAYS_PER_YEAR: i64 = DAYS_PER_MONTH * MONTHS_PER_YEAR;
+/// One Skyblock day lasts 20 real minutes.
+pub const SECONDS_PER_DAY: i64 = 1200;
+/// One Skyblock hour lasts 50 real seconds.
+pub const SECONDS_PER_HOUR: i64 = 50;
+
+/// Namespace of the name-based ids given to generated calendars and events.
+const ID_NAMESPACE: Uuid = Uuid::from_u128(0x6f1d_3c2a_8b7e_4f05_9a61_d2c4_e8b3_5a17);
+
+pub const MONTH_NAMES: [&str; 12] = [
+    "Early Spring", "Spring", "Late Spring",
+    "Early Summer", "Summer", "Late Summer",
+    "Early Autumn", "Autumn", "Late Autumn",
+    "Early Winter", "Winter", "Late Winter",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SkyblockDateTime {
     year: i16,
+    month: i8,
+    day: i8,
+    hour: i8,
+    minute: i8,
 }
-impl SkyblockDay {
-    pub fn new( day: i8, month: i8, year: i16) -> Self {
-        SkyblockDay { day, month, year }
+
+impl SkyblockDateTime {
+    pub fn new(day: i8, month: i8, year: i16) -> Self {
+        SkyblockDateTime { year, month, day, hour: 0, minute: 0 }
+    }
+
+    pub fn with_time(self, hour: i8, minute: i8) -> Self {
+        SkyblockDateTime { hour, minute, ..self }
     }
+
+    pub fn year(&self) -> i16 { self.year }
+    pub fn month(&self) -> i8 { self.month }
+    pub fn day(&self) -> i8 { self.day }
+
+    pub fn month_name(&self) -> &'static str {
+        MONTH_NAMES[(self.month - 1) as usize]
+    }
+
+    /// Number of whole Skyblock days since Early Spring 1st of year 1.
+    pub fn day_index(&self) -> i64 {
+        (self.year as i64 - 1) * DAYS_PER_YEAR + (self.month as i64 - 1) * DAYS_PER_MONTH + (self.day as i64 - 1)
+    }
+
+    fn from_day_index(index: i64) -> Self {
+        let year = index.div_euclid(DAYS_PER_YEAR) + 1;
+        let remaining_days = index.rem_euclid(DAYS_PER_YEAR);
+        let month = remaining_days / DAYS_PER_MONTH + 1;
+        let day = remaining_days % DAYS_PER_MONTH + 1;
+        SkyblockDateTime::new(day as i8, month as i8, year as i16)
+    }
+
     pub fn as_datetime(&self) -> DateTime<Utc> {
-        let total_days = ((self.year as i32 - 1) * 372) + ((self.month as i32 - 1) * 31) + (self.day as i32 -1);
-        let total_real_minutes = total_days * 20;
-        let real_duration = Duration::minutes(total_real_minutes.into());
-        const YEAR_START_TIMESTAMP: i64 = 1560275700;
+        // Rounded up to whole milliseconds, so the time maps back to the same Skyblock minute.
+        let sb_minutes = (self.day_index() * 24 + self.hour as i64) * 60 + self.minute as i64;
         let year_start = DateTime::from_timestamp(YEAR_START_TIMESTAMP, 0).unwrap();
-        year_start + real_duration
+        year_start + Duration::milliseconds((sb_minutes * 2500 + 2).div_euclid(3))
     }
 
     pub fn date_to_skyblock(date: DateTime<Utc>) -> Self {
-        const YEAR_START_TIMESTAMP: i64 = 1560275700;
-        let time_delta_minutes = date.signed_duration_since(
+        let elapsed = date.signed_duration_since(
             DateTime::from_timestamp(YEAR_START_TIMESTAMP, 0).unwrap()
-        ).num_minutes();
+        ).num_milliseconds();
 
-        let delta_days = time_delta_minutes / 20;
-        let delta_years = delta_days / 372;
-        let year = 1 + delta_years as i32;
-        let remaining_days = delta_days % 372;
-        let month = (remaining_days / 31) as i32 + 1;
-        let day = (remaining_days % 31) as i32 + 1;
+        let sb_minutes = (elapsed * 3).div_euclid(2500);
+        let sb_day = Self::from_day_index(sb_minutes.div_euclid(24 * 60));
+        let minute_of_day = sb_minutes.rem_euclid(24 * 60);
+        sb_day.with_time((minute_of_day / 60) as i8, (minute_of_day % 60) as i8)
+    }
 
-        SkyblockDay::new(day as i8, month as i8, year as i16)
+    pub fn start_of_day(&self) -> Self {
+        self.with_time(0, 0)
     }
 
-    pub fn get_events(&mut self, previous_events: &Vec<Event>, previous_elections: &Vec<Election>) -> Vec<Event> {
-        let mut events = Vec::new();
+    pub fn add_days(&self, days: i64) -> Self {
+        Self::from_day_index(self.day_index() + days).with_time(self.hour, self.minute)
+    }
+}
 
-        for event in previous_events {
-            if event.modulo(self.as_datetime()) == 0 {
-                events.push(event.clone()) // Clone the event to own it
-            }
+impl std::fmt::Display for SkyblockDateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}, Year {} {:02}:{:02}", self.month_name(), self.day, self.year, self.hour, self.minute)
+    }
+}
+
+/// Parses a Skyblock date written as `year-month-day`, e.g. `402-3-17`.
+impl std::str::FromStr for SkyblockDateTime {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid Skyblock date '{}', expected year-month-day like 402-3-17", value);
+        let mut parts = value.splitn(3, '-').map(|part| part.trim().parse::<i64>().map_err(|_| invalid()));
+        let (Some(year), Some(month), Some(day)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let (year, month, day) = (year?, month?, day?);
+        if !(1..=i16::MAX as i64).contains(&year) || !(1..=MONTHS_PER_YEAR).contains(&month) || !(1..=DAYS_PER_MONTH).contains(&day) {
+            return Err(invalid());
         }
+        Ok(SkyblockDateTime::new(day as i8, month as i8, year as i16))
+    }
+}
 
-        if let Some(election) = Self::get_election(self.year+1, previous_elections) {
-            let election_events = election.get_events();
-            for event in election_events {
-                if event.get_start_time() == self.as_datetime() {
-                    events.push(event);
-                }
-            }
+/// When a recurring Skyblock event takes place, in Skyblock calendar terms.
+#[derive(Debug, Clone, Copy)]
+pub enum Schedule {
+    /// Every `every` Skyblock days, counted from Early Spring 1st of year 1.
+    EveryDays { every: i64, offset: i64 },
+    /// On the listed days of every month.
+    Monthly { days: &'static [i8] },
+    /// On the listed `(month, day)` pairs of every year.
+    Yearly { dates: &'static [(i8, i8)] },
+}
+
+impl Schedule {
+    pub fn matches(&self, day: &SkyblockDateTime) -> bool {
+        match self {
+            Schedule::EveryDays { every, offset } => day.day_index().rem_euclid(*every) == *offset,
+            Schedule::Monthly { days } => days.contains(&day.day),
+            Schedule::Yearly { dates } => dates.contains(&(day.month, day.day)),
         }
-        events
+    }
+}
+
+/// A built-in recurring Skyblock event.
+#[derive(Debug, Clone, Copy)]
+pub struct EventRule {
+    pub key: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    pub schedule: Schedule,
+    /// Skyblock hour of the day the event starts at.
+    pub hour: i8,
+    /// Length of the event in real seconds.
+    pub duration: i64,
+    pub color: &'static str,
+}
+
+impl EventRule {
+    /// Start time of the occurrence on `day`, if the rule takes place that day.
+    pub fn start_on(&self, day: &SkyblockDateTime) -> Option<DateTime<Utc>> {
+        self.schedule.matches(day).then(|| day.with_time(self.hour, 0).as_datetime())
     }
 
-    fn get_next_skyblock_day(date: DateTime<Utc>) -> DateTime<Utc> {
-        let minute = date.minute();
-        let valid_minutes = [15, 35, 55];
-        for &valid_minute in &valid_minutes {
-            if minute < valid_minute {
-                return date.with_minute(valid_minute).unwrap().with_second(0).unwrap();
+    pub fn event_at(&self, start: DateTime<Utc>) -> Event {
+        Event::new(
+            self.title.to_string(),
+            self.description.to_string(),
+            start - Duration::seconds(REMIND_BEFORE),
+            start,
+            start + Duration::seconds(self.duration),
+            self.duration,
+            0,
+            REMIND_BEFORE,
+        )
+        .with_id(event_id(self.key, start))
+        .with_category(Category::Skyblock)
+        .with_tags(&["skyblock", self.key])
+        .with_color(self.color)
+        .with_icon(self.key)
+    }
+}
+
+pub(crate) const REMIND_BEFORE: i64 = 120;
+
+/// Id derived from `name`, identical every time the calendar is regenerated.
+pub fn stable_id(name: &str) -> Uuid {
+    Uuid::new_v5(&ID_NAMESPACE, name.as_bytes())
+}
+
+/// Id of the generated Skyblock calendar.
+pub fn calendar_id() -> Uuid {
+    stable_id("skyblock")
+}
+
+/// Id of the occurrence of `key` starting at `start`: a version 8 UUID whose first six bytes
+/// hold the start, so [`find_event`] can regenerate the event from the id alone.
+pub fn event_id(key: &str, start: DateTime<Utc>) -> Uuid {
+    let mut bytes = *stable_id(&format!("{}@{}", key, start.timestamp())).as_bytes();
+    bytes[..6].copy_from_slice(&start.timestamp().to_be_bytes()[2..]);
+    Builder::from_custom_bytes(bytes).into_uuid()
+}
+
+/// Start of the occurrence `id` names, if it is a Skyblock event id.
+fn event_start(id: Uuid) -> Option<DateTime<Utc>> {
+    if id.get_version_num() != 8 {
+        return None;
+    }
+    let mut seconds = [0; 8];
+    seconds[2..].copy_from_slice(&id.as_bytes()[..6]);
+    DateTime::from_timestamp(i64::from_be_bytes(seconds), 0).filter(|start| dated_times().contains(start))
+}
+
+/// The Skyblock event with `id`, generated again around its start. Unlike the stored
+/// calendar, this finds events however far they are from now.
+pub fn find_event(id: Uuid) -> Option<Event> {
+    let start = event_start(id)?;
+    generate_calendar(start, start + Duration::seconds(1)).get_event(id).cloned()
+}
+
+pub const RULES: &[EventRule] = &[
+    EventRule {
+        key: "dark_auction",
+        title: "Dark Auction",
+        description: "Sirius holds the Dark Auction in the Wilderness.",
+        schedule: Schedule::EveryDays { every: 3, offset: 0 },
+        hour: 0,
+        duration: SECONDS_PER_DAY,
+        color: "#5B2C6F",
+    },
+    EventRule {
+        key: JACOBS_CONTEST,
+        title: "Jacob's Farming Contest",
+        description: "A farming contest on three crops, hosted by Jacob.",
+        schedule: Schedule::EveryDays { every: 3, offset: 1 },
+        hour: 0,
+        duration: SECONDS_PER_DAY,
+        color: "#D4AC0D",
+    },
+    EventRule {
+        key: "bank_interest",
+        title: "Bank Interest",
+        description: "Interest is paid out to every bank account.",
+        schedule: Schedule::EveryDays { every: 93, offset: 0 },
+        hour: 0,
+        duration: 0,
+        color: "#F1C40F",
+    },
+    EventRule {
+        key: "cult_of_the_fallen_star",
+        title: "Cult of the Fallen Star",
+        description: "The Cult gathers at the Dwarven Mines to summon a fallen star.",
+        schedule: Schedule::Monthly { days: &[7, 14, 21, 28] },
+        hour: 0,
+        duration: 6 * SECONDS_PER_HOUR,
+        color: "#8E44AD",
+    },
+    EventRule {
+        key: "hoppitys_hunt",
+        title: "Hoppity's Hunt",
+        description: "Chocolate Rabbit eggs spawn all over Skyblock.",
+        schedule: Schedule::Yearly { dates: &[(1, 1)] },
+        hour: 0,
+        duration: 3 * DAYS_PER_MONTH * SECONDS_PER_DAY,
+        color: "#E67E22",
+    },
+    EventRule {
+        key: "election_booth",
+        title: "Election Booth",
+        description: "The election booth is open in the Hub, vote for next year's Mayor.",
+        schedule: Schedule::Yearly { dates: &[(6, 27)] },
+        hour: 0,
+        duration: 279 * SECONDS_PER_DAY,
+        color: "#2980B9",
+    },
+    EventRule {
+        key: "travelling_zoo",
+        title: "Travelling Zoo",
+        description: "Oringo visits the Hub with a new legendary pet.",
+        schedule: Schedule::Yearly { dates: &[(4, 1), (10, 1)] },
+        hour: 0,
+        duration: 3 * SECONDS_PER_DAY,
+        color: "#27AE60",
+    },
+    EventRule {
+        key: "spooky_festival",
+        title: "Spooky Festival",
+        description: "Trick or Treat! Spooky mobs and candy appear everywhere.",
+        schedule: Schedule::Yearly { dates: &[(8, 29)] },
+        hour: 0,
+        duration: 3 * SECONDS_PER_DAY,
+        color: "#D35400",
+    },
+    EventRule {
+        key: "jerrys_workshop",
+        title: "Jerry's Workshop",
+        description: "Jerry's Workshop is open for the whole of Late Winter.",
+        schedule: Schedule::Yearly { dates: &[(12, 1)] },
+        hour: 0,
+        duration: DAYS_PER_MONTH * SECONDS_PER_DAY,
+        color: "#3498DB",
+    },
+    EventRule {
+        key: "season_of_jerry",
+        title: "Season of Jerry",
+        description: "Defend Jerry's Workshop from Jerry's foes.",
+        schedule: Schedule::Yearly { dates: &[(12, 24)] },
+        hour: 0,
+        duration: 3 * SECONDS_PER_DAY,
+        color: "#1ABC9C",
+    },
+    EventRule {
+        key: "new_year_celebration",
+        title: "New Year Celebration",
+        description: "Grab a free New Year Cake from the baker in the Hub.",
+        schedule: Schedule::Yearly { dates: &[(12, 29)] },
+        hour: 0,
+        duration: 3 * SECONDS_PER_DAY,
+        color: "#E74C3C",
+    },
+];
+
+/// The real times that have a Skyblock date; years are counted in an i16.
+pub fn dated_times() -> Range<DateTime<Utc>> {
+    SkyblockDateTime::new(1, 1, 1).as_datetime()..SkyblockDateTime::new(1, 1, i16::MAX).as_datetime()
+}
+
+/// Whether a Jacob's Farming Contest starts at `start`, with the perks of the mayor in office applied.
+pub fn contest_starts_at(start: DateTime<Utc>) -> bool {
+    if !dated_times().contains(&start) {
+        return false;
+    }
+    let day = SkyblockDateTime::date_to_skyblock(start).start_of_day();
+    let Some(rule) = RULES.iter().find(|rule| rule.key == JACOBS_CONTEST) else {
+        return false;
+    };
+    let election = ELECTION_HISTORY.read().unwrap().elections().into_iter().find(|election| election.in_office(&day));
+    let rule = election.map_or(*rule, |election| election.apply(rule));
+    rule.start_on(&day) == Some(start)
+}
+
+/// Perks of the mayor and minister in office at `date`; none outside [`dated_times`].
+pub fn mayor_perks_at(date: DateTime<Utc>) -> Vec<Perk> {
+    if !dated_times().contains(&date) {
+        return Vec::new();
+    }
+    let day = SkyblockDateTime::date_to_skyblock(date);
+    ELECTION_HISTORY.read().unwrap()
+        .elections()
+        .into_iter()
+        .find(|election| election.in_office(&day))
+        .map(|election| election.perks)
+        .unwrap_or_default()
+}
+
+/// Builds the Skyblock calendar for `[from, to)` from the built-in [`RULES`].
+///
+/// Events that started before `from` but are still running are included. When the election
+/// history knows the mayor in office, or can predict the next one, all of their perks are
+/// applied on top.
+pub fn generate_calendar(from: DateTime<Utc>, to: DateTime<Utc>) -> Calendar {
+    let mut calendar = Calendar::new("Skyblock".to_string(), None).with_id(calendar_id());
+    let dated = dated_times();
+    let to = to.min(dated.end);
+    let from = from.max(dated.start);
+    if from >= to {
+        return calendar;
+    }
+    let contests = JACOB_CONTESTS.read().unwrap();
+    let elections = ELECTION_HISTORY.read().unwrap().elections();
+    let in_window = |event: &Event| event.overlaps(Some(from), Some(to));
+
+    // No event lasts longer than a Skyblock year, so look back that far for ongoing ones.
+    let mut day = SkyblockDateTime::date_to_skyblock(from).add_days(-DAYS_PER_YEAR).start_of_day();
+    while day.as_datetime() < to {
+        let election = elections.iter().find(|election| election.in_office(&day));
+
+        for rule in RULES {
+            let rule = election.map_or(*rule, |election| election.apply(rule));
+            let Some(start) = rule.start_on(&day) else { continue };
+
+            let mut event = rule.event_at(start);
+            if !in_window(&event) {
+                continue;
             }
+            if rule.key == JACOBS_CONTEST {
+                if let Some(crops) = contests.crops_at(start) {
+                    event = event.with_crops(crops.clone());
+                }
+                if election.is_some_and(|election| election.perks.contains(&Perk::Goated)) {
+                    event = event.with_note("Finnegan's GOATed perk is active: every bracket includes 10% more players.");
+                }
+            }
+            calendar.add_event(event);
         }
-        date.checked_add_signed(Duration::hours(1)).unwrap().with_minute(15).unwrap().with_second(0).unwrap()
-    }
 
-    fn get_election(sb_year: i16, previous_elections: &Vec<Election>) -> Option<&Election> {
-        for election in previous_elections {
-            if election.year == sb_year {
-                return Some(election);
+        for event in election.map(|election| election.get_events(&day)).unwrap_or_default() {
+            if in_window(&event) {
+                calendar.add_event(event);
             }
         }
-        None
+        day = day.add_days(1);
     }
+    calendar
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Election {
-    mayor: String,
-    minister: String,
-    perks: Vec<String>,
-    year: i16,
-    start: DateTime<Utc>,
-    end: DateTime<Utc>
-}
-
-impl Election {
-
-    async fn get_ongoing_election() -> Election {
-        let response = reqwest::get("https://api.hypixel.net/v2/resources/skyblock/election")
-            .await
-            .unwrap()
-            .json::<Value>()
-            .await
-            .unwrap();
-
-        let mayor = response["mayor"].clone();
-        let mayor_name = mayor["name"].as_str().unwrap().to_string();
-
-        let year = response["year"].as_i64().unwrap() as i16;
-        let minister = mayor["minister"].clone();
-        let minister_name = minister["name"].as_str().unwrap().to_string();
-
-        let perks: Vec<String> = mayor["perks"].as_array().unwrap().iter()
-            .chain(minister["perks"].as_array().unwrap().iter())
-            .map(|perk| perk["name"].as_str().unwrap().to_string())
-            .collect();
-
-        let start: DateTime<Utc> = SkyblockDay::new(27,5,year).as_datetime();
-        let end: DateTime<Utc> = SkyblockDay::date_to_skyblock(start + Duration::seconds(403200)).as_datetime();
-        Election { mayor: mayor_name, minister: minister_name, perks, year, start, end }
-    }
-
-    fn get_events(&self) -> Vec<Event> {
-        let mut events = Vec::new();
-
-        let create_event = |name: &str, start: DateTime<Utc>, duration_hours: i64, interval: i64| {
-            Event::new(
-                name.to_string(),
-                "".to_string(),
-                self.start - Duration::minutes(3),
-                start,
-                start + Duration::hours(duration_hours),
-                interval,
-                0,
-                120
-            )
-        };
+    #[test]
+    fn event_ids_carry_their_start() {
+        let start = SkyblockDateTime::new(2, 1, 400).as_datetime();
+        let id = event_id(JACOBS_CONTEST, start);
+        assert_eq!(id, event_id(JACOBS_CONTEST, start));
+        assert_ne!(id, event_id("dark_auction", start));
+        assert_eq!(event_start(id), Some(start));
+        assert_eq!(event_start(stable_id("skyblock")), None);
+        assert_eq!(event_start(Uuid::new_v4()), None);
+    }
+
+    #[test]
+    fn events_are_found_far_from_the_stored_window() {
+        for year in [1, 400, 20000] {
+            let day = SkyblockDateTime::new(5, 7, year);
+            let calendar = generate_calendar(day.as_datetime(), day.add_days(1).as_datetime());
+            let events = calendar.list_events();
+            assert!(!events.is_empty());
+            for event in events {
+                let found = find_event(event.get_id()).unwrap_or_else(|| panic!("{} was not found", event));
+                assert_eq!(found.start_time, event.start_time);
+                assert_eq!(found.title(), event.title());
+            }
+        }
+        assert!(find_event(event_id(JACOBS_CONTEST, SkyblockDateTime::new(3, 1, 400).as_datetime())).is_none());
+    }
 
-        if self.perks.contains(&"Fishing Festival".to_string()) {
-            events.extend((5..15).map(|i| {
-                let start = SkyblockDay::new(1, i % 12, self.year).as_datetime();
-                create_event("Fishing Festival", start, 1, 3600)
-            }));
-        } else if self.perks.contains(&"Mining Fiesta".to_string()) {
-            events.extend((0..3).map(|i| {
-                let start = SkyblockDay::new(1, i % 12, self.year).as_datetime();
-                create_event("Mining Fiesta", start, 5, 18000)
-            }));
-        } else if self.perks.contains(&"Mythological Ritual".to_string()) {
-            events.push(create_event("Mythological Ritual", self.start, 5, 446400));
-        } else if self.perks.contains(&"Chivalrous Carnival".to_string()) {
-            events.push(create_event("Chivalrous Carnival", self.start, 5, 446400));
+    #[test]
+    fn dates_round_trip_through_real_time() {
+        assert_eq!(SkyblockDateTime::new(1, 1, 1).as_datetime().timestamp(), YEAR_START_TIMESTAMP);
+        for date in [
+            SkyblockDateTime::new(1, 1, 1),
+            SkyblockDateTime::new(31, 12, 1),
+            SkyblockDateTime::new(17, 3, 402).with_time(13, 30),
+            SkyblockDateTime::new(17, 3, 402).with_time(13, 31),
+            SkyblockDateTime::new(17, 3, 402).with_time(13, 32),
+            SkyblockDateTime::new(1, 1, i16::MAX - 1).with_time(23, 59),
+        ] {
+            assert_eq!(SkyblockDateTime::date_to_skyblock(date.as_datetime()), date, "{}", date);
         }
+        // A Skyblock day is 20 real minutes and an hour 50 seconds.
+        let day = SkyblockDateTime::new(17, 3, 402);
+        assert_eq!(day.add_days(1).as_datetime() - day.as_datetime(), Duration::seconds(SECONDS_PER_DAY));
+        assert_eq!(day.with_time(1, 0).as_datetime() - day.as_datetime(), Duration::seconds(SECONDS_PER_HOUR));
+        // Times within a Skyblock minute belong to it.
+        let minute = day.with_time(6, 7);
+        assert_eq!(SkyblockDateTime::date_to_skyblock(minute.as_datetime() + Duration::milliseconds(800)), minute);
+    }
 
-        events
+    #[test]
+    fn days_are_added_across_months_and_years() {
+        let last = SkyblockDateTime::new(31, 12, 401).with_time(5, 0);
+        assert_eq!(last.add_days(1), SkyblockDateTime::new(1, 1, 402).with_time(5, 0));
+        assert_eq!(last.add_days(-DAYS_PER_YEAR), SkyblockDateTime::new(31, 12, 400).with_time(5, 0));
+        assert_eq!(SkyblockDateTime::new(31, 1, 402).add_days(1), SkyblockDateTime::new(1, 2, 402));
+        assert_eq!(SkyblockDateTime::new(1, 1, 1).day_index(), 0);
+        assert_eq!(SkyblockDateTime::new(1, 1, 2).day_index(), DAYS_PER_YEAR);
     }
-}
 
-pub fn generate_calendar(from: DateTime<Utc>, to: DateTime<Utc>) -> Calendar {
-    let mut calendar = Calendar::new("Skyblock".to_string(), None);
-    let mut next_valid_day = SkyblockDay::get_next_skyblock_day(from);
-    let previous_events:Vec<Event> = read_json_from_file("skyblock_events.json").unwrap();
-    let previous_elections:Vec<Election> = read_json_from_file("elections.json").unwrap();
-
-    while next_valid_day < to {
-        let mut sb_day = SkyblockDay::date_to_skyblock(next_valid_day);
-        let sb_events = sb_day.get_events(&previous_events, &previous_elections);
-        for event in sb_events {
-            calendar.add_event(event)
+    #[test]
+    fn dates_parse_as_year_month_day() {
+        assert_eq!("402-3-17".parse(), Ok(SkyblockDateTime::new(17, 3, 402)));
+        assert_eq!(" 1 - 1 - 1 ".parse(), Ok(SkyblockDateTime::new(1, 1, 1)));
+        for invalid in ["402-13-1", "402-1-32", "0-1-1", "40000-1-1", "402-3", "402-3-x", ""] {
+            assert!(invalid.parse::<SkyblockDateTime>().is_err(), "{} was accepted", invalid);
         }
-        next_valid_day += Duration::minutes(20)
     }
-    calendar
-}
\ No newline at end of file
+
+    #[test]
+    fn schedules_match_their_days() {
+        let every_third = Schedule::EveryDays { every: 3, offset: 1 };
+        assert!(every_third.matches(&SkyblockDateTime::new(2, 1, 1)));
+        assert!(!every_third.matches(&SkyblockDateTime::new(3, 1, 1)));
+        // Counted across month ends rather than restarting every month.
+        assert!(every_third.matches(&SkyblockDateTime::new(1, 2, 1)));
+        assert!(Schedule::Monthly { days: &[7, 14] }.matches(&SkyblockDateTime::new(14, 9, 300)));
+        assert!(!Schedule::Yearly { dates: &[(8, 29)] }.matches(&SkyblockDateTime::new(29, 9, 300)));
+    }
+
+    #[test]
+    fn generated_calendars_include_running_events() {
+        // Hoppity's Hunt starts on Early Spring 1st and lasts three months.
+        let from = SkyblockDateTime::new(10, 2, 402).as_datetime();
+        let calendar = generate_calendar(from, from + Duration::seconds(SECONDS_PER_DAY));
+        let hunt = calendar.list_events().into_iter().find(|event| event.has_tag("hoppitys_hunt")).unwrap();
+        assert_eq!(hunt.start_time, SkyblockDateTime::new(1, 1, 402).as_datetime());
+        assert!(calendar.list_events().iter().all(|event| event.overlaps(Some(from), Some(from + Duration::seconds(SECONDS_PER_DAY)))));
+
+        assert!(generate_calendar(dated_times().end, dated_times().end + Duration::days(1)).list_events().is_empty());
+        assert!(generate_calendar(from, from).list_events().is_empty());
+        assert!(mayor_perks_at(DateTime::<Utc>::MAX_UTC).is_empty());
+    }
+}
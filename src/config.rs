@@ -0,0 +1,580 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use clap::Parser;
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::api::rate_limit::{Limit, RateLimits};
+use crate::calendar::skyblock::SECONDS_PER_DAY;
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+const MIN_POLL_SECS: u64 = 10;
+/// Printed in place of secrets.
+const MASK: &str = "********";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// The effective configuration; defaults until [`init`] is called from `main`.
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+pub fn init(config: Config) {
+    if CONFIG.set(config).is_err() {
+        panic!("the configuration was already initialised");
+    }
+}
+
+/// Path of a data file inside the configured data directory.
+pub fn data_file(name: &str) -> PathBuf {
+    config().storage.data_dir.join(name)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub log: LogConfig,
+    pub hypixel: HypixelConfig,
+    pub polling: PollingConfig,
+    pub health: HealthConfig,
+    pub rate_limits: RateLimits,
+    pub notifications: Vec<NotificationSink>,
+    pub admin: AdminConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: IpAddr,
+    pub port: u16,
+    pub tls: Option<TlsConfig>,
+    /// How long a shutdown may take in all: draining open requests, stopping background tasks and saving state.
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig { bind: IpAddr::V4(Ipv4Addr::LOCALHOST), port: 7878, tls: None, shutdown_timeout_secs: 30 }
+    }
+}
+
+impl ServerConfig {
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain.
+    pub cert: PathBuf,
+    /// PEM private key.
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Directory holding users, tokens, elections and contest data.
+    pub data_dir: PathBuf,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig { data_dir: PathBuf::from(".") }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub file: PathBuf,
+    /// One of `off`, `error`, `warn`, `info`, `debug` or `trace`.
+    pub level: String,
+    pub format: LogFormat,
+    /// Levels overriding `level` for a module path and everything below it, e.g. `"warp" = "warn"`.
+    pub modules: BTreeMap<String, String>,
+    pub rotation: LogRotation,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            file: PathBuf::from("server.log"),
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            modules: BTreeMap::new(),
+            rotation: LogRotation::default(),
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn level_filter(&self) -> LevelFilter {
+        self.level.parse().unwrap_or(LevelFilter::Info)
+    }
+
+    pub fn module_filters(&self) -> impl Iterator<Item = (&str, LevelFilter)> {
+        self.modules.iter().filter_map(|(module, level)| Some((module.as_str(), level.parse().ok()?)))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format '{}', expected text or json", s)),
+        }
+    }
+}
+
+/// When the log file is moved aside, as `<file>.<timestamp>`, and how many old files are kept.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogRotation {
+    pub by: RotateBy,
+    /// Only used when rotating by size.
+    pub max_size_mb: u64,
+    pub keep: usize,
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        LogRotation { by: RotateBy::Size, max_size_mb: 50, keep: 5 }
+    }
+}
+
+impl LogRotation {
+    pub fn max_bytes(&self) -> u64 {
+        self.max_size_mb * 1024 * 1024
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RotateBy {
+    Never,
+    Size,
+    Daily,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HypixelConfig {
+    pub base_url: String,
+    /// Sent as `API-Key`; only needed for authenticated endpoints.
+    pub api_key: Option<String>,
+}
+
+impl Default for HypixelConfig {
+    fn default() -> Self {
+        HypixelConfig { base_url: "https://api.hypixel.net".to_string(), api_key: None }
+    }
+}
+
+impl HypixelConfig {
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), path.trim_start_matches('/'))
+    }
+}
+
+/// Poll intervals, in seconds.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PollingConfig {
+    pub election_secs: u64,
+    pub auctions_secs: u64,
+    pub bazaar_secs: u64,
+    /// How often the TLS certificate and key are checked for changes.
+    pub tls_reload_secs: u64,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        PollingConfig { election_secs: 300, auctions_secs: 60, bazaar_secs: 60, tls_reload_secs: 30 }
+    }
+}
+
+impl PollingConfig {
+    pub fn election(&self) -> Duration {
+        Duration::from_secs(self.election_secs)
+    }
+
+    pub fn auctions(&self) -> Duration {
+        Duration::from_secs(self.auctions_secs)
+    }
+
+    pub fn bazaar(&self) -> Duration {
+        Duration::from_secs(self.bazaar_secs)
+    }
+
+    pub fn tls_reload(&self) -> Duration {
+        Duration::from_secs(self.tls_reload_secs)
+    }
+}
+
+/// Thresholds after which `/health/ready` reports the instance as not ready.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Seconds since the last successful election poll.
+    pub max_election_age_secs: u64,
+    pub max_auctions_age_secs: u64,
+    pub max_bazaar_age_secs: u64,
+    /// Seconds since the scheduler last regenerated the Skyblock calendar, which it does every Skyblock day.
+    pub max_scheduler_age_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            max_election_age_secs: 900,
+            max_auctions_age_secs: 300,
+            max_bazaar_age_secs: 300,
+            max_scheduler_age_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Ids of the users that may get tokens with privileged scopes. Ids are assigned on
+    /// registration, so unlike names they cannot be claimed by whoever registers first.
+    pub users: Vec<Uuid>,
+}
+
+impl AdminConfig {
+    pub fn is_admin(&self, user: Uuid) -> bool {
+        self.users.contains(&user)
+    }
+}
+
+/// Where event reminders are delivered.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum NotificationSink {
+    Discord { webhook_url: String },
+    Webhook { url: String },
+}
+
+impl NotificationSink {
+    pub fn url(&self) -> &str {
+        match self {
+            NotificationSink::Discord { webhook_url } => webhook_url,
+            NotificationSink::Webhook { url } => url,
+        }
+    }
+
+    /// The sink with only the scheme and host of its URL left, as the rest is the secret.
+    fn masked(&self) -> Self {
+        let url = self.url();
+        let host = url.split_once("://").map(|(scheme, rest)| (scheme, rest.split(['/', '?']).next().unwrap_or_default()));
+        let masked = match host {
+            Some((scheme, host)) => format!("{}://{}/{}", scheme, host, MASK),
+            None => MASK.to_string(),
+        };
+        match self {
+            NotificationSink::Discord { .. } => NotificationSink::Discord { webhook_url: masked },
+            NotificationSink::Webhook { .. } => NotificationSink::Webhook { url: masked },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, source: Box<toml::de::Error> },
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => write!(f, "could not read {}: {}", path.display(), source),
+            ConfigError::Parse { path, source } => write!(f, "invalid config file {}: {}", path.display(), source),
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Command line flags; each one can also be set through the environment variable shown in `--help`.
+/// Flags override the environment, which overrides the config file.
+#[derive(Debug, Parser)]
+#[command(version, about = "Hypixel Skyblock calendar and market API")]
+pub struct Cli {
+    /// TOML config file [default: config.toml, if present]
+    #[arg(short, long, env = "SKYBLOCK_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "SKYBLOCK_BIND")]
+    pub bind: Option<IpAddr>,
+    #[arg(short, long, env = "SKYBLOCK_PORT")]
+    pub port: Option<u16>,
+    #[arg(long, env = "SKYBLOCK_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    #[arg(long, env = "SKYBLOCK_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    #[arg(long, env = "SKYBLOCK_SHUTDOWN_TIMEOUT_SECS")]
+    pub shutdown_timeout_secs: Option<u64>,
+    #[arg(long, env = "SKYBLOCK_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+    #[arg(long, env = "SKYBLOCK_LOG_FILE")]
+    pub log_file: Option<PathBuf>,
+    #[arg(long, env = "SKYBLOCK_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// text or json
+    #[arg(long, env = "SKYBLOCK_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+    #[arg(long, env = "HYPIXEL_API_URL")]
+    pub hypixel_url: Option<String>,
+    #[arg(long, env = "HYPIXEL_API_KEY", hide_env_values = true)]
+    pub hypixel_key: Option<String>,
+    #[arg(long, env = "SKYBLOCK_ELECTION_POLL_SECS")]
+    pub election_poll_secs: Option<u64>,
+    #[arg(long, env = "SKYBLOCK_AUCTIONS_POLL_SECS")]
+    pub auctions_poll_secs: Option<u64>,
+    #[arg(long, env = "SKYBLOCK_BAZAAR_POLL_SECS")]
+    pub bazaar_poll_secs: Option<u64>,
+    /// Print the effective configuration as TOML and exit.
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+impl Config {
+    /// Reads the config file named by `cli` (if any) and applies the flag and environment overrides.
+    pub fn load(cli: &Cli) -> Result<Config, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => Config::default(),
+        };
+        config.apply(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|source| ConfigError::Read { path: path.to_path_buf(), source })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse { path: path.to_path_buf(), source: Box::new(source) })
+    }
+
+    fn apply(&mut self, cli: &Cli) {
+        if let Some(bind) = cli.bind {
+            self.server.bind = bind;
+        }
+        if let Some(port) = cli.port {
+            self.server.port = port;
+        }
+        if let (Some(cert), Some(key)) = (&cli.tls_cert, &cli.tls_key) {
+            self.server.tls = Some(TlsConfig { cert: cert.clone(), key: key.clone() });
+        }
+        if let Some(secs) = cli.shutdown_timeout_secs {
+            self.server.shutdown_timeout_secs = secs;
+        }
+        if let Some(data_dir) = &cli.data_dir {
+            self.storage.data_dir = data_dir.clone();
+        }
+        if let Some(file) = &cli.log_file {
+            self.log.file = file.clone();
+        }
+        if let Some(level) = &cli.log_level {
+            self.log.level = level.clone();
+        }
+        if let Some(format) = cli.log_format {
+            self.log.format = format;
+        }
+        if let Some(url) = &cli.hypixel_url {
+            self.hypixel.base_url = url.clone();
+        }
+        if let Some(key) = &cli.hypixel_key {
+            self.hypixel.api_key = Some(key.clone());
+        }
+        if let Some(secs) = cli.election_poll_secs {
+            self.polling.election_secs = secs;
+        }
+        if let Some(secs) = cli.auctions_poll_secs {
+            self.polling.auctions_secs = secs;
+        }
+        if let Some(secs) = cli.bazaar_poll_secs {
+            self.polling.bazaar_secs = secs;
+        }
+    }
+
+    /// Collects every problem instead of stopping at the first one.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let is_http = |url: &str| url.starts_with("http://") || url.starts_with("https://");
+
+        if self.server.port == 0 {
+            problems.push("server.port must not be 0".to_string());
+        }
+        if let Some(tls) = &self.server.tls {
+            for (name, path) in [("cert", &tls.cert), ("key", &tls.key)] {
+                if !path.is_file() {
+                    problems.push(format!("server.tls.{} {} is not a file", name, path.display()));
+                }
+            }
+        }
+        if self.server.shutdown_timeout_secs == 0 {
+            problems.push("server.shutdown_timeout_secs must be at least 1".to_string());
+        }
+        if self.storage.data_dir.exists() && !self.storage.data_dir.is_dir() {
+            problems.push(format!("storage.data_dir {} is not a directory", self.storage.data_dir.display()));
+        }
+        if self.log.level.parse::<LevelFilter>().is_err() {
+            problems.push(format!("log.level '{}' must be one of off, error, warn, info, debug, trace", self.log.level));
+        }
+        for (module, level) in &self.log.modules {
+            if level.parse::<LevelFilter>().is_err() {
+                problems.push(format!("log.modules.{} '{}' must be one of off, error, warn, info, debug, trace", module, level));
+            }
+        }
+        if self.log.rotation.by == RotateBy::Size && self.log.rotation.max_size_mb == 0 {
+            problems.push("log.rotation.max_size_mb must be at least 1".to_string());
+        }
+        if !is_http(&self.hypixel.base_url) {
+            problems.push(format!("hypixel.base_url '{}' must start with http:// or https://", self.hypixel.base_url));
+        }
+        if self.hypixel.api_key.as_deref().is_some_and(|key| key.trim().is_empty()) {
+            problems.push("hypixel.api_key must not be empty when set".to_string());
+        }
+        for (poller, every, max_age) in [
+            ("election", self.polling.election_secs, self.health.max_election_age_secs),
+            ("auctions", self.polling.auctions_secs, self.health.max_auctions_age_secs),
+            ("bazaar", self.polling.bazaar_secs, self.health.max_bazaar_age_secs),
+        ] {
+            if every < MIN_POLL_SECS {
+                problems.push(format!("polling.{}_secs must be at least {}", poller, MIN_POLL_SECS));
+            }
+            if max_age < every {
+                problems.push(format!("health.max_{}_age_secs must be at least polling.{}_secs", poller, poller));
+            }
+        }
+        if self.polling.tls_reload_secs == 0 {
+            problems.push("polling.tls_reload_secs must be at least 1".to_string());
+        }
+        if self.health.max_scheduler_age_secs < SECONDS_PER_DAY as u64 {
+            problems.push(format!("health.max_scheduler_age_secs must be at least a Skyblock day, {}s", SECONDS_PER_DAY));
+        }
+        let limits = &self.rate_limits;
+        for (group, limit) in [
+            ("calendar", limits.calendar),
+            ("auth", limits.auth),
+            ("bazaar", limits.bazaar),
+            ("auction", limits.auction),
+            ("skyblock", limits.skyblock),
+        ] {
+            let Limit { burst, per_minute } = limit;
+            if burst == 0 || per_minute == 0 {
+                problems.push(format!("rate_limits.{} burst and per_minute must be at least 1", group));
+            }
+        }
+        for (i, sink) in self.notifications.iter().enumerate() {
+            // Webhook URLs carry their own credentials, so they are not echoed back.
+            if !is_http(sink.url()) {
+                problems.push(format!("notifications[{}] url must start with http:// or https://", i));
+            }
+        }
+
+        if problems.is_empty() { Ok(()) } else { Err(ConfigError::Invalid(problems)) }
+    }
+
+    /// The configuration as TOML, with secrets masked.
+    pub fn to_toml(&self) -> String {
+        let mut printable = self.clone();
+        if printable.hypixel.api_key.is_some() {
+            printable.hypixel.api_key = Some(MASK.to_string());
+        }
+        printable.notifications = self.notifications.iter().map(NotificationSink::masked).collect();
+        toml::to_string_pretty(&printable).expect("the configuration is always serializable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn flags_override_the_file() {
+        let mut config = parse("[server]\nport = 8000\n[polling]\nauctions_secs = 120\nbazaar_secs = 90\n");
+        let cli = Cli::try_parse_from(["api", "--port", "9000", "--bazaar-poll-secs", "30"]).unwrap();
+        config.apply(&cli);
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.polling.auctions_secs, 120);
+        assert_eq!(config.polling.bazaar_secs, 30);
+        assert_eq!(config.polling.election_secs, PollingConfig::default().election_secs);
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert!(toml::from_str::<Config>("[server]\nprot = 8000\n").is_err());
+        assert!(toml::from_str::<Config>("[[notifications]]\ntype = \"discord\"\nurl = \"https://x\"\n").is_err());
+        assert!(toml::from_str::<Config>("[admin]\nusers = [\"alice\"]\n").is_err());
+    }
+
+    #[test]
+    fn validation_reports_every_problem_without_secrets() {
+        assert!(Config::default().validate().is_ok());
+        let config = parse(concat!(
+            "[server]\nport = 0\n",
+            "[polling]\nauctions_secs = 5\n",
+            "[[notifications]]\ntype = \"webhook\"\nurl = \"ftp://example.com/secret-token\"\n",
+        ));
+        let Err(ConfigError::Invalid(problems)) = config.validate() else {
+            panic!("the configuration was accepted");
+        };
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems.iter().all(|problem| !problem.contains("secret-token")));
+    }
+
+    #[test]
+    fn printed_config_masks_secrets() {
+        let config = parse(concat!(
+            "[hypixel]\napi_key = \"hypixel-key\"\n",
+            "[[notifications]]\ntype = \"discord\"\nwebhook_url = \"https://discord.com/api/webhooks/1/discord-token\"\n",
+            "[[notifications]]\ntype = \"webhook\"\nurl = \"https://example.com?key=webhook-token\"\n",
+        ));
+        let printed = config.to_toml();
+        for secret in ["hypixel-key", "discord-token", "webhook-token"] {
+            assert!(!printed.contains(secret), "{} in {}", secret, printed);
+        }
+        assert!(printed.contains("https://discord.com/********"));
+        assert!(printed.contains("https://example.com/********"));
+        assert_eq!(parse(&printed).notifications.len(), 2);
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+use chrono::Utc;
+use serde::Serialize;
+use crate::config::config;
+use crate::metrics::METRICS;
+
+pub static HEALTH: LazyLock<Health> = LazyLock::new(Health::default);
+
+/// The task that regenerates the Skyblock calendar, checked like a poller.
+pub const SCHEDULER: &str = "scheduler";
+
+/// How loading a data file went.
+#[derive(Debug, Clone, PartialEq)]
+enum FileState {
+    Parsed,
+    /// Not there yet, which is normal on a first start.
+    Missing,
+    Invalid(String),
+}
+
+/// Background task and data file state for the readiness check.
+pub struct Health {
+    started: Instant,
+    running: Mutex<BTreeMap<&'static str, bool>>,
+    files: Mutex<BTreeMap<String, FileState>>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health { started: Instant::now(), running: Mutex::default(), files: Mutex::default() }
+    }
+}
+
+/// Marks a poller as running until dropped, so a panicking poller shows up as stopped.
+pub struct PollerGuard(&'static str);
+
+impl Drop for PollerGuard {
+    fn drop(&mut self) {
+        HEALTH.running.lock().unwrap().insert(self.0, false);
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ok,
+    /// Not done yet, but still within its grace period.
+    Pending,
+    Stale,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub status: Status,
+    /// Whether a failure makes the instance unready.
+    pub critical: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age_secs: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Check {
+    fn new(status: Status, critical: bool) -> Self {
+        Check { status, critical, age_secs: None, detail: None }
+    }
+
+    fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    fn is_failing(&self) -> bool {
+        matches!(self.status, Status::Stale | Status::Failed)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: BTreeMap<String, Check>,
+}
+
+impl Health {
+    pub fn uptime_secs(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    pub fn poller_started(&self, poller: &'static str) -> PollerGuard {
+        self.running.lock().unwrap().insert(poller, true);
+        PollerGuard(poller)
+    }
+
+    /// Records reading or writing a data file; a successful write fixes an unparsable file.
+    pub fn record_file(&self, path: &Path, result: Result<(), String>) {
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let state = match result {
+            Ok(()) => FileState::Parsed,
+            Err(_) if !path.exists() => FileState::Missing,
+            Err(e) => FileState::Invalid(e),
+        };
+        self.files.lock().unwrap().insert(name, state);
+    }
+
+    fn running_check(&self, task: &str) -> Check {
+        match self.running.lock().unwrap().get(task).copied().unwrap_or(false) {
+            true => Check::new(Status::Ok, true),
+            false => Check::new(Status::Failed, true).detail("not running"),
+        }
+    }
+
+    /// How long ago `task` last succeeded; before its first success it is pending until `max_age` has passed.
+    fn age_check(&self, task: &str, max_age: u64) -> Check {
+        let max_age = max_age as i64;
+        let mut check = match METRICS.last_success(task) {
+            Some(at) => {
+                let age = Utc::now().timestamp() - at;
+                let mut check = Check::new(if age > max_age { Status::Stale } else { Status::Ok }, true);
+                check.age_secs = Some(age);
+                check
+            }
+            None if self.uptime_secs() as i64 <= max_age => Check::new(Status::Pending, true),
+            None => Check::new(Status::Stale, true),
+        };
+        if check.status == Status::Stale {
+            check = check.detail(format!("no successful run in the last {}s", max_age));
+        }
+        check
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        let mut checks = BTreeMap::new();
+        checks.insert("storage".to_string(), storage_check(&config().storage.data_dir));
+
+        let health = &config().health;
+        for (poller, max_age) in [
+            ("election", health.max_election_age_secs),
+            ("auctions", health.max_auctions_age_secs),
+            ("bazaar", health.max_bazaar_age_secs),
+        ] {
+            checks.insert(format!("{}_poller", poller), self.running_check(poller));
+            checks.insert(format!("{}_poll", poller), self.age_check(poller, max_age));
+        }
+        checks.insert("scheduler".to_string(), self.running_check(SCHEDULER));
+        checks.insert("scheduler_run".to_string(), self.age_check(SCHEDULER, health.max_scheduler_age_secs));
+
+        for (file, state) in self.files.lock().unwrap().iter() {
+            let check = match state {
+                FileState::Parsed => Check::new(Status::Ok, true),
+                FileState::Missing => Check::new(Status::Ok, true).detail("not created yet"),
+                FileState::Invalid(e) => Check::new(Status::Failed, true).detail(e.clone()),
+            };
+            checks.insert(format!("file:{}", file), check);
+        }
+
+        let ready = !checks.values().any(|check| check.critical && check.is_failing());
+        Readiness { ready, checks }
+    }
+}
+
+/// The data directory can be written to. Every probe writes its own file, so concurrent
+/// checks don't remove each other's.
+fn storage_check(data_dir: &Path) -> Check {
+    static PROBES: AtomicU64 = AtomicU64::new(0);
+    let probe = data_dir.join(format!(".health-{}-{}", std::process::id(), PROBES.fetch_add(1, Ordering::Relaxed)));
+    match std::fs::write(&probe, b"ok").and_then(|()| std::fs::remove_file(&probe)) {
+        Ok(()) => Check::new(Status::Ok, true),
+        Err(e) => Check::new(Status::Failed, true).detail(format!("{}: {}", data_dir.display(), e)),
+    }
+}
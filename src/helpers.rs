@@ -1,11 +1,83 @@
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufReader, BufWriter};
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
+use std::time::Instant;
 use serde::de::{DeserializeOwned, Error};
+use serde::Serialize;
+use crate::config::config;
+use crate::health::HEALTH;
+use crate::metrics::METRICS;
+
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+fn file_label(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
 
 pub fn read_json_from_file<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T, serde_json::Error> {
-    let file = File::open(path.as_ref()).map_err(|e| serde_json::Error::custom(format!("File error: {}", e)))?;
-    let reader = BufReader::new(file);
-    let data = serde_json::from_reader(reader)?;
-    Ok(data)
-}
\ No newline at end of file
+    let started = Instant::now();
+    let data = File::open(path.as_ref())
+        .map_err(|e| serde_json::Error::custom(format!("File error: {}", e)))
+        .and_then(|file| serde_json::from_reader(BufReader::new(file)));
+    HEALTH.record_file(path.as_ref(), data.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+    METRICS.record_storage(&file_label(path.as_ref()), "read", started.elapsed());
+    data
+}
+
+/// Writes `data` to a temporary file next to `path` and renames it into place, so a crash
+/// part-way through leaves the previous file intact.
+pub fn write_json_to_file<T: Serialize, P: AsRef<Path>>(path: P, data: &T) -> Result<(), serde_json::Error> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let started = Instant::now();
+    let path = path.as_ref();
+    let temp = path.with_file_name(format!(
+        ".{}.{}-{}.tmp", file_label(path), std::process::id(), WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = write_json(&temp, data).and_then(|()| {
+        std::fs::rename(&temp, path).map_err(|e| serde_json::Error::custom(format!("File error: {}", e)))
+    });
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    written?;
+    HEALTH.record_file(path, Ok(()));
+    METRICS.record_storage(&file_label(path), "write", started.elapsed());
+    Ok(())
+}
+
+fn write_json<T: Serialize>(path: &Path, data: &T) -> Result<(), serde_json::Error> {
+    let file_error = |e: io::Error| serde_json::Error::custom(format!("File error: {}", e));
+    let file = File::create(path).map_err(file_error)?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, data)?;
+    writer.into_inner().map_err(|e| file_error(e.into_error()))?.sync_all().map_err(file_error)
+}
+
+/// GETs `path` on the configured Hypixel API, with the API key when one is set.
+/// Error statuses are returned as errors.
+pub async fn hypixel_get(path: &str) -> Result<reqwest::Response, reqwest::Error> {
+    let hypixel = &config().hypixel;
+    let mut request = HTTP_CLIENT.get(hypixel.url(path));
+    if let Some(key) = &hypixel.api_key {
+        request = request.header("API-Key", key);
+    }
+
+    let started = Instant::now();
+    let response = request.send().await.and_then(reqwest::Response::error_for_status);
+    let outcome = match &response {
+        Ok(_) => "ok".to_string(),
+        Err(e) => e.status().map_or_else(|| "transport".to_string(), |status| status.as_u16().to_string()),
+    };
+    // Pages and other query parameters would each get their own series.
+    let label = path.split('?').next().unwrap_or(path);
+    METRICS.record_hypixel_call(label, outcome, started.elapsed());
+    response
+}
+
+/// POSTs `body` as JSON to `url`. Error statuses are returned as errors.
+pub async fn post_json<T: Serialize>(url: &str, body: &T) -> Result<(), reqwest::Error> {
+    HTTP_CLIENT.post(url).json(body).send().await?.error_for_status()?;
+    Ok(())
+}
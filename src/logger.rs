@@ -1,38 +1,221 @@
-use chrono::Local;
+use chrono::{DateTime, Local, NaiveDate};
 use env_logger::Builder;
-use std::fs::OpenOptions;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
 use std::io::Write;
-use std::sync::Mutex;
-
-pub fn init_logger() {
-    let log_file_path = "server.log";
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(log_file_path)
-        .unwrap();
-    let file = Mutex::new(file);
-
-    Builder::new()
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use crate::config::{LogConfig, LogFormat, LogRotation, RotateBy};
+
+tokio::task_local! {
+    /// Id of the request being handled, added to every log line written while handling it.
+    pub static REQUEST_ID: String;
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// The level for everything and the overrides per module path, as in the `[log]` config.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LogLevels {
+    #[schema(example = "debug")]
+    pub level: String,
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+}
+
+/// env_logger fixes its filter when built, so the filtering logger is rebuilt and swapped
+/// whenever the levels change.
+struct Logger {
+    format: LogFormat,
+    file: Arc<Mutex<RotatingFile>>,
+    levels: RwLock<LogLevels>,
+    inner: RwLock<env_logger::Logger>,
+}
+
+impl Logger {
+    fn build(&self, level: LevelFilter, modules: &[(String, LevelFilter)]) -> env_logger::Logger {
+        filtering_logger(self.format, self.file.clone(), level, modules)
+    }
+}
+
+fn filtering_logger(
+    format: LogFormat,
+    file: Arc<Mutex<RotatingFile>>,
+    level: LevelFilter,
+    modules: &[(String, LevelFilter)],
+) -> env_logger::Logger {
+    let mut builder = Builder::new();
+    builder
         .format(move |buf, record| {
-            let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-            writeln!(
-                buf,
-                "{} [{}] - {}",
-                timestamp,
-                record.level(),
-                record.args()
-            )?;
-            let mut file = file.lock().unwrap();
-            writeln!(
-                file,
-                "{} [{}] - {}",
-                timestamp,
-                record.level(),
-                record.args()
-            )
+            let line = format_line(format, Local::now(), record);
+            writeln!(buf, "{}", line)?;
+            file.lock().unwrap().write_line(&line)
         })
-        .filter(None, log::LevelFilter::Info)
-        .init();
+        .filter(None, level);
+    for (module, level) in modules {
+        builder.filter(Some(module), *level);
+    }
+    builder.build()
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.read().unwrap().log(record)
+    }
+
+    fn flush(&self) {}
 }
 
+fn parse_levels(levels: &LogLevels) -> Result<(LevelFilter, Vec<(String, LevelFilter)>), String> {
+    let parse = |level: &str| {
+        level.parse::<LevelFilter>()
+            .map_err(|_| format!("'{}' must be one of off, error, warn, info, debug, trace", level))
+    };
+    let modules = levels.modules.iter()
+        .map(|(module, level)| Ok((module.clone(), parse(level)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok((parse(&levels.level)?, modules))
+}
+
+pub fn init_logger(config: &LogConfig) -> std::io::Result<()> {
+    let file = Arc::new(Mutex::new(RotatingFile::open(&config.file, config.rotation.clone())?));
+    let levels = LogLevels { level: config.level.clone(), modules: config.modules.clone() };
+    let module_filters: Vec<_> = config.module_filters().map(|(module, level)| (module.to_string(), level)).collect();
+
+    let inner = filtering_logger(config.format, file.clone(), config.level_filter(), &module_filters);
+    let max_level = inner.filter();
+    let logger = LOGGER.get_or_init(|| Logger { format: config.format, file, levels: RwLock::new(levels), inner: RwLock::new(inner) });
+    log::set_logger(logger).map_err(std::io::Error::other)?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+pub fn log_levels() -> Option<LogLevels> {
+    Some(LOGGER.get()?.levels.read().unwrap().clone())
+}
+
+/// Replaces the log levels until the next restart.
+pub fn set_log_levels(levels: LogLevels) -> Result<(), String> {
+    let logger = LOGGER.get().ok_or("logging is not initialised")?;
+    let (level, modules) = parse_levels(&levels)?;
+    let rebuilt = logger.build(level, &modules);
+    log::set_max_level(rebuilt.filter());
+    *logger.inner.write().unwrap() = rebuilt;
+    *logger.levels.write().unwrap() = levels;
+    Ok(())
+}
+
+fn format_line(format: LogFormat, now: DateTime<Local>, record: &Record) -> String {
+    let request_id = current_request_id();
+    match format {
+        LogFormat::Text => {
+            let timestamp = now.format("%Y-%m-%d %H:%M:%S");
+            match request_id {
+                Some(id) => format!("{} [{}] [{}] - {}", timestamp, record.level(), id, record.args()),
+                None => format!("{} [{}] - {}", timestamp, record.level(), record.args()),
+            }
+        }
+        LogFormat::Json => {
+            let mut line = serde_json::json!({
+                "timestamp": now.to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            if let Some(id) = request_id {
+                line["request_id"] = serde_json::Value::String(id);
+            }
+            line.to_string()
+        }
+    }
+}
+
+/// The log file, moved aside to `<file>.<timestamp>` when it grows too big or a new day starts.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    opened_on: NaiveDate,
+    rotation: LogRotation,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, rotation: LogRotation) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        let opened_on = metadata.modified()
+            .map(|modified| DateTime::<Local>::from(modified).date_naive())
+            .unwrap_or_else(|_| Local::now().date_naive());
+        Ok(RotatingFile { path: path.to_path_buf(), file, size: metadata.len(), opened_on, rotation })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let now = Local::now();
+        let due = match self.rotation.by {
+            RotateBy::Never => false,
+            RotateBy::Size => self.size > 0 && self.size + line.len() as u64 + 1 > self.rotation.max_bytes(),
+            RotateBy::Daily => now.date_naive() != self.opened_on,
+        };
+        if due {
+            // Logging must never take the server down, so a failed rotation just keeps appending.
+            if let Err(e) = self.rotate(now) {
+                eprintln!("Failed to rotate {}: {}", self.path.display(), e);
+            }
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self, now: DateTime<Local>) -> std::io::Result<()> {
+        let stamp = match self.rotation.by {
+            RotateBy::Daily => self.opened_on.format("%Y-%m-%d").to_string(),
+            _ => now.format("%Y-%m-%d-%H%M%S").to_string(),
+        };
+        let mut rotated = PathBuf::from(format!("{}.{}", self.path.display(), stamp));
+        let mut n = 1;
+        while rotated.exists() {
+            rotated = PathBuf::from(format!("{}.{}.{}", self.path.display(), stamp, n));
+            n += 1;
+        }
+        std::fs::rename(&self.path, &rotated)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        self.opened_on = now.date_naive();
+        self.prune()
+    }
+
+    /// Deletes the oldest rotated files beyond `keep`.
+    fn prune(&self) -> std::io::Result<()> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let Some(name) = self.path.file_name().and_then(|name| name.to_str()) else {
+            return Ok(());
+        };
+        let prefix = format!("{}.", name);
+        let mut rotated: Vec<_> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_str().is_some_and(|file| file.starts_with(&prefix)))
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect();
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.rotation.keep);
+        for (_, path) in rotated.into_iter().take(excess) {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
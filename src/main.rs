@@ -1,20 +1,165 @@
 mod helpers;
 mod calendar;
 mod api;
+mod auth;
+mod config;
+mod health;
 mod logger;
+mod metrics;
+mod notifications;
+mod server;
+mod shutdown;
+mod snapshots;
+mod tls;
 
-use log::info;
+use std::process::ExitCode;
+use std::time::Instant;
+use clap::Parser;
+use log::{error, info, warn};
+use crate::calendar::database::DATABASE;
+use crate::calendar::election;
+use crate::config::{Cli, Config};
 use crate::logger::init_logger;
+use crate::shutdown::Shutdown;
 
 #[tokio::main]
-async fn main() {
-    init_logger();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    if cli.print_config {
+        print!("{}", config.to_toml());
+        return ExitCode::SUCCESS;
+    }
+    let certificates = match config.server.tls.as_ref().map(tls::Certificates::load).transpose() {
+        Ok(certificates) => certificates,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let acceptor = match certificates.clone().map(tls::acceptor).transpose() {
+        Ok(acceptor) => acceptor,
+        Err(e) => {
+            eprintln!("Error: could not set up TLS: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    if let Err(e) = std::fs::create_dir_all(&config.storage.data_dir) {
+        eprintln!("Error: could not create data directory {}: {}", config.storage.data_dir.display(), e);
+        return ExitCode::FAILURE;
+    }
+    if let Err(e) = init_logger(&config.log) {
+        eprintln!("Error: could not open log file {}: {}", config.log.file.display(), e);
+        return ExitCode::FAILURE;
+    }
+    config::init(config);
+    let config = config::config();
+
+    // Load everything up front so broken data files show up in /health/ready right away.
+    std::sync::LazyLock::force(&auth::TOKENS);
+    std::sync::LazyLock::force(&auth::DUMMY_HASH);
+    std::sync::LazyLock::force(&calendar::jacob::JACOB_CONTESTS);
+    std::sync::LazyLock::force(&notifications::DELIVERIES);
+    // Starting without the accounts or tokens would overwrite them with the next saved change.
+    if let Some(e) = DATABASE.read().unwrap().load_error() {
+        error!("Not starting, the users could not be loaded: {}", e);
+        return ExitCode::FAILURE;
+    }
+    if let Some(e) = auth::TOKENS.read().unwrap().load_error() {
+        error!("Not starting, the API tokens could not be loaded: {}", e);
+        return ExitCode::FAILURE;
+    }
+    let event_count: usize = DATABASE.read().unwrap()
+        .list_users()
+        .into_iter()
+        .flat_map(|user| user.list_calendars())
+        .map(|calendar| calendar.list_events().len())
+        .sum();
+    let (days, hours, minutes) = seconds_to_dhm(calendar::database::CALENDAR_SPAN_MINUTES * 60);
+    info!("Generated {} Skyblock events for the next {}d {}h {}m", event_count, days, hours, minutes);
+
+    let started = Instant::now();
+    let shutdown = Shutdown::new();
+    let mut pollers = vec![
+        tokio::spawn(election::poll_elections(config.polling.election(), shutdown.subscribe())),
+        tokio::spawn(api::poll_auctions(config.polling.auctions(), shutdown.subscribe())),
+        tokio::spawn(api::poll_bazaar(config.polling.bazaar(), shutdown.subscribe())),
+        tokio::spawn(calendar::database::schedule_skyblock_calendar(shutdown.subscribe())),
+        tokio::spawn(notifications::schedule_reminders(shutdown.subscribe())),
+        tokio::spawn(notifications::deliver_reminders(shutdown.subscribe())),
+    ];
+    if let Some(certificates) = certificates {
+        pollers.push(tokio::spawn(tls::watch_certificates(certificates, config.polling.tls_reload(), shutdown.subscribe())));
+    }
+
     let api = api::build_routes();
 
-    info!("API Server starting on http://localhost:7878");
-    warp::serve(api)
-        .run(([127, 0, 0, 1], 7878))
-        .await;
+    let scheme = if acceptor.is_some() { "https" } else { "http" };
+    info!("API Server starting on {}://{}", scheme, config.server.addr());
+    // Draining requests, stopping the pollers, delivering queued reminders and flushing state
+    // all share one deadline.
+    let stop = async {
+        let signal = shutdown::wait_for_signal().await;
+        info!("Received {}, shutting down", signal);
+        shutdown.trigger();
+        Instant::now() + config.server.shutdown_timeout()
+    };
+    let summary = match server::serve(api, config.server.addr(), acceptor, stop).await {
+        Ok(summary) => summary,
+        Err(e) => {
+            error!("API Server stopped: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let deadline = tokio::time::Instant::from_std(summary.deadline);
+
+    let pollers_stopped = tokio::time::timeout_at(deadline, async {
+        for poller in pollers {
+            let _ = poller.await;
+        }
+    }).await.is_ok();
+    if !pollers_stopped {
+        warn!("Background pollers did not stop before the shutdown deadline");
+    }
+    let reminders_delivered = tokio::time::timeout_at(deadline, notifications::deliver_queued()).await.is_ok();
+    if !reminders_delivered {
+        warn!("{} queued reminders were not delivered before the shutdown deadline", notifications::queued());
+    }
+    let flushed = match tokio::time::timeout_at(deadline, tokio::task::spawn_blocking(shutdown::flush_state)).await {
+        Ok(Ok(failed)) => Some(failed),
+        Ok(Err(e)) => {
+            error!("Flushing state failed: {}", e);
+            None
+        }
+        Err(_) => {
+            warn!("State was not flushed before the shutdown deadline");
+            None
+        }
+    };
+
+    let (days, hours, minutes) = seconds_to_dhm(started.elapsed().as_secs() as i64);
+    info!(
+        "Shutdown complete after {}d {}h {}m: {} connections served, requests {}, pollers {}, reminders {}, {}",
+        days, hours, minutes,
+        summary.connections,
+        if summary.drained { "drained" } else { "cut off" },
+        if pollers_stopped { "stopped" } else { "abandoned" },
+        if reminders_delivered { "delivered" } else { "dropped" },
+        match flushed {
+            Some(failed) => format!("{} failed saves", failed),
+            None => "state not flushed".to_string(),
+        },
+    );
+    if flushed != Some(0) || !summary.drained || !reminders_delivered {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
 }
 
 fn seconds_to_dhm(seconds: i64) -> (i64, i64, i64) {
@@ -22,4 +167,4 @@ fn seconds_to_dhm(seconds: i64) -> (i64, i64, i64) {
     let hours = (seconds % 86400) / 3600;
     let minutes = (seconds % 3600) / 60;
     (days, hours, minutes)
-}
+}
\ No newline at end of file
@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use warp::http::StatusCode;
+use crate::api::docs::route_template;
+
+/// Upper bounds in seconds, the Prometheus client defaults.
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Cumulative counts per bucket of [`LATENCY_BUCKETS`].
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        for (count, bound) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// Process-wide counters, rendered by `GET /metrics`.
+pub struct Metrics {
+    started: Instant,
+    /// By (route, method, status).
+    requests: Mutex<BTreeMap<(String, &'static str, u16), u64>>,
+    /// By (route, method).
+    request_latency: Mutex<BTreeMap<(String, &'static str), Histogram>>,
+    /// By (path, outcome), where the outcome is `ok`, an HTTP status or `transport`.
+    hypixel_calls: Mutex<BTreeMap<(String, String), u64>>,
+    hypixel_latency: Mutex<BTreeMap<String, Histogram>>,
+    /// Unix time of the last successful run, by poller.
+    poller_success: Mutex<BTreeMap<&'static str, i64>>,
+    /// By (file, operation).
+    storage_latency: Mutex<BTreeMap<(String, &'static str), Histogram>>,
+    auction_snapshot_size: AtomicU64,
+    reminders: AtomicU64,
+    /// Reminders that could not be delivered, counted once per sink.
+    failed_deliveries: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            started: Instant::now(),
+            requests: Mutex::default(),
+            request_latency: Mutex::default(),
+            hypixel_calls: Mutex::default(),
+            hypixel_latency: Mutex::default(),
+            poller_success: Mutex::default(),
+            storage_latency: Mutex::default(),
+            auction_snapshot_size: AtomicU64::new(0),
+            reminders: AtomicU64::new(0),
+            failed_deliveries: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Labels a request by the documented route it matched, so ids and paths nobody serves don't
+/// each get their own series.
+fn route_label(path: &str) -> String {
+    route_template(path).unwrap_or_else(|| "unmatched".to_string())
+}
+
+/// Methods outside the standard ones share a label for the same reason.
+fn method_label(method: &str) -> &'static str {
+    const METHODS: [&str; 7] = ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"];
+    METHODS.into_iter().find(|known| *known == method).unwrap_or("other")
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn record_request(&self, method: &str, path: &str, status: StatusCode, elapsed: Duration) {
+        let (route, method) = (route_label(path), method_label(method));
+        *self.requests.lock().unwrap()
+            .entry((route.clone(), method, status.as_u16()))
+            .or_default() += 1;
+        self.request_latency.lock().unwrap()
+            .entry((route, method))
+            .or_default()
+            .observe(elapsed);
+    }
+
+    pub fn record_hypixel_call(&self, path: &str, outcome: String, elapsed: Duration) {
+        *self.hypixel_calls.lock().unwrap().entry((path.to_string(), outcome)).or_default() += 1;
+        self.hypixel_latency.lock().unwrap().entry(path.to_string()).or_default().observe(elapsed);
+    }
+
+    pub fn poller_succeeded(&self, poller: &'static str) {
+        self.poller_success.lock().unwrap().insert(poller, Utc::now().timestamp());
+    }
+
+    pub fn last_success(&self, poller: &str) -> Option<i64> {
+        self.poller_success.lock().unwrap().get(poller).copied()
+    }
+
+    pub fn record_storage(&self, file: &str, operation: &'static str, elapsed: Duration) {
+        self.storage_latency.lock().unwrap()
+            .entry((file.to_string(), operation))
+            .or_default()
+            .observe(elapsed);
+    }
+
+    pub fn set_auction_snapshot_size(&self, auctions: usize) {
+        self.auction_snapshot_size.store(auctions as u64, Ordering::Relaxed);
+    }
+
+    pub fn reminder_sent(&self, failed_deliveries: usize) {
+        self.reminders.fetch_add(1, Ordering::Relaxed);
+        self.failed_deliveries.fetch_add(failed_deliveries as u64, Ordering::Relaxed);
+    }
+
+    /// The Prometheus text exposition format; the tracked item counts are read by the caller.
+    pub fn render(&self, tracked_auctions: usize, tracked_bazaar: usize) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# HELP skyblock_uptime_seconds Seconds since the process started.");
+        let _ = writeln!(out, "# TYPE skyblock_uptime_seconds gauge");
+        let _ = writeln!(out, "skyblock_uptime_seconds {}", self.started.elapsed().as_secs());
+
+        let _ = writeln!(out, "# HELP skyblock_http_requests_total Requests served, by route, method and status.");
+        let _ = writeln!(out, "# TYPE skyblock_http_requests_total counter");
+        for ((route, method, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "skyblock_http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                escape(route), method, status, count
+            );
+        }
+
+        let _ = writeln!(out, "# HELP skyblock_http_request_duration_seconds Time to produce a response, by route and method.");
+        let _ = writeln!(out, "# TYPE skyblock_http_request_duration_seconds histogram");
+        for ((route, method), histogram) in self.request_latency.lock().unwrap().iter() {
+            let labels = format!("route=\"{}\",method=\"{}\"", escape(route), method);
+            histogram.render(&mut out, "skyblock_http_request_duration_seconds", &labels);
+        }
+
+        let _ = writeln!(out, "# HELP skyblock_hypixel_requests_total Hypixel API calls, by path and outcome.");
+        let _ = writeln!(out, "# TYPE skyblock_hypixel_requests_total counter");
+        for ((path, outcome), count) in self.hypixel_calls.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "skyblock_hypixel_requests_total{{path=\"{}\",outcome=\"{}\"}} {}",
+                escape(path), escape(outcome), count
+            );
+        }
+
+        let _ = writeln!(out, "# HELP skyblock_hypixel_request_duration_seconds Hypixel API latency, by path.");
+        let _ = writeln!(out, "# TYPE skyblock_hypixel_request_duration_seconds histogram");
+        for (path, histogram) in self.hypixel_latency.lock().unwrap().iter() {
+            let labels = format!("path=\"{}\"", escape(path));
+            histogram.render(&mut out, "skyblock_hypixel_request_duration_seconds", &labels);
+        }
+
+        let _ = writeln!(out, "# HELP skyblock_poller_last_success_timestamp_seconds Unix time of each poller's last successful run.");
+        let _ = writeln!(out, "# TYPE skyblock_poller_last_success_timestamp_seconds gauge");
+        for (poller, timestamp) in self.poller_success.lock().unwrap().iter() {
+            let _ = writeln!(out, "skyblock_poller_last_success_timestamp_seconds{{poller=\"{}\"}} {}", poller, timestamp);
+        }
+
+        let _ = writeln!(out, "# HELP skyblock_storage_duration_seconds Time to read or write a data file.");
+        let _ = writeln!(out, "# TYPE skyblock_storage_duration_seconds histogram");
+        for ((file, operation), histogram) in self.storage_latency.lock().unwrap().iter() {
+            let labels = format!("file=\"{}\",operation=\"{}\"", escape(file), operation);
+            histogram.render(&mut out, "skyblock_storage_duration_seconds", &labels);
+        }
+
+        let _ = writeln!(out, "# HELP skyblock_auction_snapshot_auctions Active auctions reported by the last auction fetch.");
+        let _ = writeln!(out, "# TYPE skyblock_auction_snapshot_auctions gauge");
+        let _ = writeln!(out, "skyblock_auction_snapshot_auctions {}", self.auction_snapshot_size.load(Ordering::Relaxed));
+
+        let _ = writeln!(out, "# HELP skyblock_tracked_items Items being tracked, by market.");
+        let _ = writeln!(out, "# TYPE skyblock_tracked_items gauge");
+        let _ = writeln!(out, "skyblock_tracked_items{{market=\"auction\"}} {}", tracked_auctions);
+        let _ = writeln!(out, "skyblock_tracked_items{{market=\"bazaar\"}} {}", tracked_bazaar);
+
+        let _ = writeln!(out, "# HELP skyblock_reminders_total Reminders sent to the notification sinks.");
+        let _ = writeln!(out, "# TYPE skyblock_reminders_total counter");
+        let _ = writeln!(out, "skyblock_reminders_total {}", self.reminders.load(Ordering::Relaxed));
+
+        let _ = writeln!(out, "# HELP skyblock_reminder_failed_deliveries_total Reminders a sink did not accept.");
+        let _ = writeln!(out, "# TYPE skyblock_reminder_failed_deliveries_total counter");
+        let _ = writeln!(out, "skyblock_reminder_failed_deliveries_total {}", self.failed_deliveries.load(Ordering::Relaxed));
+
+        out
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::sync::{LazyLock, Mutex, RwLock};
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use uuid::Uuid;
+use crate::calendar::calendar::{Event, EventQuery};
+use crate::calendar::database::DATABASE;
+use crate::config::{config, data_file, NotificationSink};
+use crate::helpers::{post_json, read_json_from_file, write_json_to_file};
+use crate::metrics::METRICS;
+use crate::shutdown::ShutdownSignal;
+
+const DELIVERIES_FILE: &str = "reminder_deliveries.json";
+/// How often the scheduler looks for reminders that came due.
+const CHECK_EVERY: std::time::Duration = std::time::Duration::from_secs(15);
+/// Least time between two reminders, well below Discord's limit of 30 webhook posts a minute.
+const DELIVERY_GAP: std::time::Duration = std::time::Duration::from_secs(2);
+/// Reminders are looked up by event start, so this bounds how early a reminder may be set.
+pub const MAX_REMIND_LEAD_HOURS: i64 = 24;
+/// How long deliveries are remembered; reminders older than this cannot be replayed.
+pub const KEEP_DELIVERIES_DAYS: i64 = 8;
+
+pub static DELIVERIES: LazyLock<RwLock<DeliveryLog>> = LazyLock::new(|| {
+    let log = read_json_from_file(data_file(DELIVERIES_FILE)).unwrap_or_else(|e| {
+        warn!("No reminder deliveries loaded, could not read {}: {}", DELIVERIES_FILE, e);
+        DeliveryLog::default()
+    });
+    RwLock::new(log)
+});
+
+static QUEUE: LazyLock<ReminderQueue> = LazyLock::new(ReminderQueue::default);
+/// When the last reminder went out; held while delivering, so deliveries never overlap.
+static LAST_DELIVERY: tokio::sync::Mutex<Option<Instant>> = tokio::sync::Mutex::const_new(None);
+
+/// One reminder: an occurrence of an event at the time it was due.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReminderKey {
+    pub notify_at: DateTime<Utc>,
+    pub event: Uuid,
+}
+
+impl ReminderKey {
+    pub fn of(event: &Event) -> Self {
+        ReminderKey { notify_at: event.notify_at(), event: event.get_id() }
+    }
+}
+
+/// Reminders every sink accepted, kept so they are not sent again.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DeliveryLog {
+    delivered: BTreeSet<ReminderKey>,
+}
+
+impl DeliveryLog {
+    pub fn contains(&self, key: &ReminderKey) -> bool {
+        self.delivered.contains(key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.delivered.is_empty()
+    }
+
+    /// Records a delivery and forgets those older than [`KEEP_DELIVERIES_DAYS`].
+    fn record(&mut self, key: ReminderKey, now: DateTime<Utc>) {
+        self.delivered.insert(key);
+        let oldest = ReminderKey { notify_at: now - Duration::days(KEEP_DELIVERIES_DAYS), event: Uuid::nil() };
+        self.delivered = self.delivered.split_off(&oldest);
+    }
+
+    pub fn save(&self) -> Result<(), serde_json::Error> {
+        write_json_to_file(data_file(DELIVERIES_FILE), self)?;
+        info!("Saved {} reminder deliveries to {}", self.delivered.len(), DELIVERIES_FILE);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    reminders: VecDeque<Event>,
+    keys: HashSet<ReminderKey>,
+}
+
+/// Reminders waiting to be delivered, in the order they were queued.
+#[derive(Default)]
+struct ReminderQueue {
+    pending: Mutex<Pending>,
+    ready: Notify,
+}
+
+fn payload(sink: &NotificationSink, event: &Event) -> Value {
+    match sink {
+        // Discord renders <t:...:R> as a relative time in the reader's own timezone.
+        NotificationSink::Discord { .. } => json!({
+            "content": format!("**{}** starts <t:{}:R>", event.title(), event.start_time.timestamp()),
+        }),
+        NotificationSink::Webhook { .. } => json!({ "type": "reminder", "event": event }),
+    }
+}
+
+/// Sends the reminder for `event` to every configured sink; returns how many deliveries failed.
+pub async fn send_reminder(event: &Event) -> usize {
+    let mut failed = 0;
+    for sink in &config().notifications {
+        if let Err(e) = post_json(sink.url(), &payload(sink, event)).await {
+            warn!("Failed to deliver the reminder for {}: {}", event, e);
+            failed += 1;
+        }
+    }
+    METRICS.reminder_sent(failed);
+    info!("Delivered the reminder for {} to {} of {} sinks", event, config().notifications.len() - failed, config().notifications.len());
+    failed
+}
+
+/// Checks that a reminder `remind` seconds before the start is one the scheduler will find.
+pub fn check_remind(remind: i64) -> Result<(), String> {
+    if !(0..=MAX_REMIND_LEAD_HOURS * 3600).contains(&remind) {
+        return Err(format!("remind must be between 0 and {} seconds", MAX_REMIND_LEAD_HOURS * 3600));
+    }
+    Ok(())
+}
+
+/// Reminders of public calendars that came due in `[from, to)`, oldest first. Only the events'
+/// own reminder times count, as subscriptions' overrides are personal.
+pub fn due_reminders(from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Event> {
+    let query = EventQuery {
+        from: Some(from),
+        to: Some(to + Duration::hours(MAX_REMIND_LEAD_HOURS)),
+        ..EventQuery::default()
+    };
+    let database = DATABASE.read().unwrap();
+    let mut due: Vec<Event> = database.public_calendars().into_iter()
+        .flat_map(|calendar| database.occurrences(calendar, &query))
+        .filter(|event| from <= event.notify_at() && event.notify_at() < to)
+        .collect();
+    due.sort_by_key(Event::notify_at);
+    due
+}
+
+/// Queues the reminders that were neither delivered nor queued already; returns how many were.
+pub fn enqueue(reminders: Vec<Event>) -> usize {
+    let deliveries = DELIVERIES.read().unwrap();
+    let mut pending = QUEUE.pending.lock().unwrap();
+    let mut queued = 0;
+    for reminder in reminders {
+        let key = ReminderKey::of(&reminder);
+        if deliveries.contains(&key) || !pending.keys.insert(key) {
+            continue;
+        }
+        pending.reminders.push_back(reminder);
+        queued += 1;
+    }
+    if queued > 0 {
+        QUEUE.ready.notify_one();
+    }
+    queued
+}
+
+/// Reminders still waiting to be delivered.
+pub fn queued() -> usize {
+    QUEUE.pending.lock().unwrap().reminders.len()
+}
+
+/// Delivers the queued reminders one by one, at most one per [`DELIVERY_GAP`], and records
+/// those every sink accepted. Returns how many were delivered.
+pub async fn deliver_queued() -> usize {
+    let mut last_delivery = LAST_DELIVERY.lock().await;
+    let mut delivered = 0;
+    loop {
+        // Taken off the queue only once sent, so a shutdown cut-off leaves it counted as queued.
+        let Some(reminder) = QUEUE.pending.lock().unwrap().reminders.front().cloned() else {
+            break;
+        };
+        if let Some(last) = *last_delivery {
+            tokio::time::sleep_until(last + DELIVERY_GAP).await;
+        }
+        let failed = send_reminder(&reminder).await;
+        *last_delivery = Some(Instant::now());
+
+        let key = ReminderKey::of(&reminder);
+        {
+            let mut pending = QUEUE.pending.lock().unwrap();
+            pending.reminders.pop_front();
+            pending.keys.remove(&key);
+        }
+        // Partly failed reminders stay unrecorded, so they can be replayed.
+        if failed == 0 {
+            DELIVERIES.write().unwrap().record(key, Utc::now());
+            delivered += 1;
+        }
+    }
+    if delivered > 0 {
+        let saved = tokio::task::spawn_blocking(|| DELIVERIES.read().unwrap().save()).await;
+        if let Ok(Err(e)) = saved {
+            error!("Failed to save the reminder deliveries: {}", e);
+        }
+    }
+    delivered
+}
+
+/// Queues every reminder of a public calendar as it comes due. Reminders due while the server
+/// was down are not queued here; admins replay them.
+pub async fn schedule_reminders(mut shutdown: ShutdownSignal) {
+    if config().notifications.is_empty() {
+        info!("No notification sinks are configured, reminders are not scheduled");
+        return;
+    }
+    let mut checked = Utc::now();
+    let mut interval = tokio::time::interval(CHECK_EVERY);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.recv() => {
+                info!("Reminder scheduler stopped");
+                return;
+            }
+        }
+        let now = Utc::now();
+        enqueue(due_reminders(checked, now));
+        checked = now;
+    }
+}
+
+/// Delivers queued reminders as they arrive. What is still queued at shutdown is delivered by
+/// `main` within the shutdown deadline.
+pub async fn deliver_reminders(mut shutdown: ShutdownSignal) {
+    loop {
+        tokio::select! {
+            _ = QUEUE.ready.notified() => {}
+            _ = shutdown.recv() => {
+                info!("Reminder delivery stopped");
+                return;
+            }
+        }
+        deliver_queued().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reminders_are_at_most_a_day_early() {
+        assert!(check_remind(0).is_ok());
+        assert!(check_remind(MAX_REMIND_LEAD_HOURS * 3600).is_ok());
+        assert!(check_remind(Duration::days(2).num_seconds()).is_err());
+        assert!(check_remind(-1).is_err());
+    }
+
+    #[test]
+    fn old_deliveries_are_forgotten() {
+        let now = Utc::now();
+        let key = |days_ago: i64| ReminderKey { notify_at: now - Duration::days(days_ago), event: Uuid::new_v4() };
+        let (old, recent) = (key(KEEP_DELIVERIES_DAYS + 1), key(1));
+
+        let mut log = DeliveryLog::default();
+        log.record(old, now - Duration::days(KEEP_DELIVERIES_DAYS));
+        log.record(recent, now);
+        assert!(!log.contains(&old));
+        assert!(log.contains(&recent));
+        assert!(!log.contains(&ReminderKey { event: Uuid::new_v4(), ..recent }));
+    }
+}
@@ -0,0 +1,150 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use futures_util::StreamExt;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use log::{debug, error, info, warn};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower_service::Service;
+use uuid::Uuid;
+use warp::http::{HeaderMap, HeaderValue};
+use warp::{Filter, Reply};
+use crate::api::stream::Streamed;
+use crate::logger::REQUEST_ID;
+use crate::metrics::METRICS;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+/// Clients that don't finish the TLS handshake by then are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Address of the connected client, available to filters through `warp::ext`.
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteAddr(pub SocketAddr);
+
+/// The client's `X-Request-Id` if it is sane, a fresh one otherwise.
+fn request_id(headers: &HeaderMap) -> String {
+    headers.get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().simple().to_string())
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The response body; a [`Streamed`] one is sent in place of what warp built.
+fn into_body(mut response: warp::reply::Response) -> hyper::Response<UnsyncBoxBody<Bytes, BoxError>> {
+    match Streamed::take(&mut response) {
+        Some(stream) => response.map(|_| {
+            StreamBody::new(stream.map(|chunk| chunk.map(Frame::data).map_err(BoxError::from))).boxed_unsync()
+        }),
+        None => response.map(|body| body.map_err(BoxError::from).boxed_unsync()),
+    }
+}
+
+/// How the server went down.
+#[derive(Debug, Clone, Copy)]
+pub struct ServeSummary {
+    pub connections: u64,
+    /// False when open connections were still busy at the deadline and got dropped.
+    pub drained: bool,
+    /// When the whole shutdown has to be done by, as set when it started.
+    pub deadline: Instant,
+}
+
+/// Serves `filter` like `warp::serve`, but tags every request with the client's [`RemoteAddr`]
+/// and runs it with a request id, which is logged and echoed back as `X-Request-Id`.
+///
+/// With `tls` connections are HTTPS, negotiating HTTP/2 or HTTP/1.1 through ALPN; without it
+/// both are served in plain text. Once `shutdown` resolves to the shutdown's deadline no new connections
+/// are accepted, and open ones get until then to finish their requests.
+pub async fn serve<F>(
+    filter: F,
+    addr: SocketAddr,
+    tls: Option<TlsAcceptor>,
+    shutdown: impl Future<Output = Instant>,
+) -> std::io::Result<ServeSummary>
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let listener = TcpListener::bind(addr).await?;
+    let graceful = GracefulShutdown::new();
+    let mut connections = 0;
+    tokio::pin!(shutdown);
+
+    let deadline = loop {
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(connection) => connection,
+                Err(e) => {
+                    debug!("Failed to accept a connection: {}", e);
+                    continue;
+                }
+            },
+            deadline = &mut shutdown => break deadline,
+        };
+        connections += 1;
+
+        let routes = warp::service(filter.clone());
+        let service = service_fn(move |mut request: hyper::Request<Incoming>| {
+            request.extensions_mut().insert(RemoteAddr(remote));
+            let id = request_id(request.headers());
+            let method = request.method().clone();
+            let path = request.uri().path().to_string();
+            let mut routes = routes.clone();
+            REQUEST_ID.scope(id.clone(), async move {
+                let started = Instant::now();
+                let mut response = routes.call(request).await?;
+                METRICS.record_request(method.as_str(), &path, response.status(), started.elapsed());
+                if let Ok(value) = HeaderValue::from_str(&id) {
+                    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+                Ok::<_, Infallible>(into_body(response))
+            })
+        });
+        let tls = tls.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let served = match tls {
+                Some(acceptor) => {
+                    let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => stream,
+                        Ok(Err(e)) => {
+                            debug!("TLS handshake with {} failed: {}", remote, e);
+                            return;
+                        }
+                        Err(_) => {
+                            debug!("TLS handshake with {} timed out", remote);
+                            return;
+                        }
+                    };
+                    watcher.watch(builder.serve_connection_with_upgrades(TokioIo::new(stream), service).into_owned()).await
+                }
+                None => watcher.watch(builder.serve_connection_with_upgrades(TokioIo::new(stream), service).into_owned()).await,
+            };
+            if let Err(e) = served {
+                error!("Connection from {} failed: {}", remote, e);
+            }
+        });
+    };
+
+    drop(listener);
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    info!("Stopped accepting connections, waiting up to {:.1}s for open requests", remaining.as_secs_f64());
+    let drained = tokio::time::timeout_at(deadline.into(), graceful.shutdown()).await.is_ok();
+    if !drained {
+        warn!("Open connections did not finish within {:.1}s and were dropped", remaining.as_secs_f64());
+    }
+    Ok(ServeSummary { connections, drained, deadline })
+}
@@ -0,0 +1,90 @@
+use log::{error, info};
+use tokio::sync::watch;
+use crate::auth::TOKENS;
+use crate::calendar::database::DATABASE;
+use crate::calendar::election::ELECTION_HISTORY;
+use crate::calendar::jacob::JACOB_CONTESTS;
+use crate::notifications::DELIVERIES;
+
+/// Tells background tasks to stop.
+pub struct Shutdown {
+    sender: watch::Sender<bool>,
+}
+
+/// Handed to background tasks; resolves once shutdown starts.
+#[derive(Clone)]
+pub struct ShutdownSignal {
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Shutdown { sender: watch::channel(false).0 }
+    }
+
+    pub fn subscribe(&self) -> ShutdownSignal {
+        ShutdownSignal { receiver: self.sender.subscribe() }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+}
+
+impl ShutdownSignal {
+    pub async fn recv(&mut self) {
+        // An error means the sender is gone, which is a shutdown too.
+        let _ = self.receiver.wait_for(|stopping| *stopping).await;
+    }
+}
+
+/// Waits for Ctrl-C / SIGINT or SIGTERM and returns its name.
+pub async fn wait_for_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+            _ = terminate.recv() => "SIGTERM",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
+
+/// Saves every piece of persisted state, on shutdown or when an admin asks; returns how many saves failed.
+///
+/// Empty stores are skipped so a file that failed to load is never overwritten with nothing.
+pub fn flush_state() -> usize {
+    let database = DATABASE.read().unwrap();
+    let tokens = TOKENS.read().unwrap();
+    let elections = ELECTION_HISTORY.read().unwrap();
+    let contests = JACOB_CONTESTS.read().unwrap();
+    let deliveries = DELIVERIES.read().unwrap();
+    let saves = [
+        ("users", database.has_accounts().then(|| database.save())),
+        ("API tokens", (!tokens.is_empty()).then(|| tokens.save())),
+        ("election history", (!elections.is_empty()).then(|| elections.save())),
+        ("Jacob's contests", (!contests.contests.is_empty()).then(|| contests.save())),
+        ("reminder deliveries", (!deliveries.is_empty()).then(|| deliveries.save())),
+    ];
+
+    let mut failed = 0;
+    for (name, result) in saves {
+        let Some(result) = result else {
+            continue;
+        };
+        match result {
+            Ok(()) => info!("Flushed {}", name),
+            Err(e) => {
+                error!("Failed to flush {}: {}", name, e);
+                failed += 1;
+            }
+        }
+    }
+    failed
+}
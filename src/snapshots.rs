@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use chrono::{DateTime, Duration, Utc};
+use crate::calendar::skyblock::{SkyblockDateTime, SECONDS_PER_DAY};
+
+/// How soon to look again when data was due to change but hasn't yet.
+const RETRY_SECS: i64 = 5;
+
+pub static SNAPSHOTS: LazyLock<Snapshots> = LazyLock::new(Snapshots::default);
+
+/// Data that responses are built from and that changes on a known schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Snapshot {
+    /// Calendars and the users' sharing, subscriptions and preferences; changed by users and
+    /// regenerated every Skyblock day.
+    Calendar,
+    Bazaar,
+    Auctions,
+}
+
+impl Snapshot {
+    fn name(self) -> &'static str {
+        match self {
+            Snapshot::Calendar => "calendar",
+            Snapshot::Bazaar => "bazaar",
+            Snapshot::Auctions => "auctions",
+        }
+    }
+
+    /// How often the data is expected to change; Hypixel publishes market data on a fixed cycle.
+    fn refresh_every(self) -> Duration {
+        match self {
+            Snapshot::Calendar => Duration::seconds(SECONDS_PER_DAY),
+            Snapshot::Bazaar => Duration::seconds(20),
+            Snapshot::Auctions => Duration::seconds(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+    generation: u64,
+    /// When the data last changed.
+    updated: DateTime<Utc>,
+    /// When it was last fetched, changed or not.
+    checked: DateTime<Utc>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State { generation: 0, updated: DateTime::UNIX_EPOCH, checked: DateTime::UNIX_EPOCH }
+    }
+}
+
+/// Which data a response was built from and how long it stays current.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// A weak entity tag: it names the data, which is the same whatever the content coding.
+    pub etag: String,
+    pub modified: DateTime<Utc>,
+    /// When the data is next expected to change.
+    pub expires: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct Snapshots {
+    states: Mutex<HashMap<Snapshot, State>>,
+}
+
+impl Snapshots {
+    /// Records a change made here, e.g. a new event or a regenerated calendar.
+    pub fn changed(&self, snapshot: Snapshot) {
+        let now = Utc::now();
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(snapshot).or_default();
+        *state = State { generation: state.generation + 1, updated: now, checked: now };
+    }
+
+    /// Drops the current version so cached responses are rebuilt from freshly fetched data.
+    pub fn invalidate(&self, snapshot: Snapshot) {
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(snapshot).or_default();
+        state.generation += 1;
+        state.checked = DateTime::UNIX_EPOCH;
+    }
+
+    /// Records data fetched from Hypixel, last updated there at `updated`.
+    pub fn fetched(&self, snapshot: Snapshot, updated: DateTime<Utc>) {
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(snapshot).or_default();
+        if state.updated != updated {
+            state.generation += 1;
+            state.updated = updated;
+        }
+        state.checked = Utc::now();
+    }
+
+    pub fn version(&self, snapshot: Snapshot, now: DateTime<Utc>) -> Version {
+        let state = self.states.lock().unwrap().get(&snapshot).copied().unwrap_or_default();
+        match snapshot {
+            // Occurrences are generated per Skyblock day, so every day is a new version.
+            Snapshot::Calendar => {
+                let day = SkyblockDateTime::date_to_skyblock(now).start_of_day().as_datetime();
+                Version {
+                    etag: format!("W/\"{}-{}-{}\"", snapshot.name(), state.generation, day.timestamp()),
+                    modified: state.updated.max(day),
+                    expires: day + snapshot.refresh_every(),
+                }
+            }
+            Snapshot::Bazaar | Snapshot::Auctions => Version {
+                etag: format!("W/\"{}-{}\"", snapshot.name(), state.generation),
+                modified: state.updated,
+                expires: (state.updated + snapshot.refresh_every()).max(state.checked + Duration::seconds(RETRY_SECS)),
+            },
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use log::{error, info};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use crate::config::TlsConfig;
+use crate::shutdown::ShutdownSignal;
+
+/// The certificate handed to every client, swapped in place when its files change.
+#[derive(Debug)]
+pub struct Certificates {
+    files: TlsConfig,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl Certificates {
+    pub fn load(files: &TlsConfig) -> Result<Arc<Certificates>, String> {
+        let current = load_certified_key(files)?;
+        Ok(Arc::new(Certificates { files: files.clone(), current: RwLock::new(Arc::new(current)) }))
+    }
+
+    /// Reads the files again; the certificate in use stays if they are broken.
+    fn reload(&self) -> Result<(), String> {
+        let loaded = load_certified_key(&self.files)?;
+        *self.current.write().unwrap() = Arc::new(loaded);
+        Ok(())
+    }
+
+    /// Modification times of the certificate and key, to notice a renewal.
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        Some((modified(&self.files.cert)?, modified(&self.files.key)?))
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn load_certified_key(files: &TlsConfig) -> Result<CertifiedKey, String> {
+    let open = |path: &Path| {
+        File::open(path).map(BufReader::new).map_err(|e| format!("could not read {}: {}", path.display(), e))
+    };
+    let chain = rustls_pemfile::certs(&mut open(&files.cert)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid certificate {}: {}", files.cert.display(), e))?;
+    if chain.is_empty() {
+        return Err(format!("no certificate in {}", files.cert.display()));
+    }
+    let key = rustls_pemfile::private_key(&mut open(&files.key)?)
+        .map_err(|e| format!("invalid private key {}: {}", files.key.display(), e))?
+        .ok_or_else(|| format!("no private key in {}", files.key.display()))?;
+    let key = provider().key_provider.load_private_key(key)
+        .map_err(|e| format!("unsupported private key {}: {}", files.key.display(), e))?;
+
+    let certified = CertifiedKey::new(chain, key);
+    certified.keys_match()
+        .map_err(|e| format!("{} does not belong to {}: {}", files.key.display(), files.cert.display(), e))?;
+    Ok(certified)
+}
+
+/// Accepts TLS connections with the current certificate, offering HTTP/2 and HTTP/1.1.
+pub fn acceptor(certificates: Arc<Certificates>) -> Result<TlsAcceptor, String> {
+    let mut config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_cert_resolver(certificates);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Reloads the certificate whenever its files change, so a renewal needs no restart.
+pub async fn watch_certificates(certificates: Arc<Certificates>, every: Duration, mut shutdown: ShutdownSignal) {
+    let mut seen = certificates.modified();
+    let mut interval = tokio::time::interval(every);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.recv() => {
+                info!("Certificate watcher stopped");
+                return;
+            }
+        }
+        let modified = certificates.modified();
+        if modified.is_none() || modified == seen {
+            continue;
+        }
+        // Remembered even on failure: a renewal writing one file at a time changes them again.
+        seen = modified;
+        match certificates.reload() {
+            Ok(()) => info!("Reloaded the TLS certificate from {}", certificates.files.cert.display()),
+            Err(e) => error!("Failed to reload the TLS certificate, still serving the old one: {}", e),
+        }
+    }
+}